@@ -0,0 +1,32 @@
+/* C ABI of classi-cine, built with:
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ */
+#ifndef CLASSI_CINE_H
+#define CLASSI_CINE_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CcModel CcModel;
+
+/* Load the library and labels given by command line options, NULL on errors. */
+CcModel *cc_model_load(int argc, const char *const *argv);
+
+/* The score of a file, higher for likely deletes, NaN on errors. */
+double cc_score_path(const CcModel *model, const char *path);
+
+/* Train on a label, 0 for delete and 1 for keep. 0 on success, -1 on errors. */
+int cc_train_label(CcModel *model, const char *path, int label);
+
+/* Append the labels trained since loading or the last save to the label files. */
+int cc_model_save(CcModel *model);
+
+void cc_model_free(CcModel *model);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
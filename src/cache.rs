@@ -0,0 +1,238 @@
+use crate::tokenizer::{Ngram, NgramTokens};
+use crate::walk::FileMeta;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+// Bump when the on-disk format or tokenization output changes.
+const VERSION: u32 = 2;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    meta: FileMeta,
+    tokens: Vec<String>,
+}
+
+// The vocabulary built from the entries and the ngram ids of each file, which make up most of
+// the startup time. Valid while the entries and the vocabulary settings are unchanged.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Vocab {
+    // The settings the vocabulary was built with.
+    pub key: String,
+    // The token strings in id order, from 1.
+    pub tokens: Vec<String>,
+    // The tokens of each ngram in id order, from 1.
+    pub ngrams: Vec<NgramTokens>,
+    pub idf: Option<Vec<f64>>,
+    pub files: HashMap<PathBuf, Vec<Ngram>>,
+}
+
+// The file count of every token and ngram of the library, frequent or not, to update the
+// vocabulary for added and removed files without counting every file again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Counts {
+    // The settings the counts were made with.
+    pub key: String,
+    // The files counted.
+    pub paths: HashSet<PathBuf>,
+    pub tokens: HashMap<String, usize>,
+    // The token strings the ngrams are made of, in id order, from 1.
+    pub vocab: Vec<String>,
+    pub ngrams: Vec<(NgramTokens, usize)>,
+}
+
+// Tokenization results keyed by path, valid while the file size and modification time match.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+
+    version: u32,
+    // The tokenizer settings the entries were created with.
+    #[serde(default)]
+    key: String,
+    entries: HashMap<PathBuf, Entry>,
+    // Perceptual hashes, independent of the tokenizer settings.
+    #[serde(default)]
+    phashes: HashMap<PathBuf, (FileMeta, Vec<u64>)>,
+    // Durations in seconds from ffprobe, likewise independent of the tokenizer settings.
+    #[serde(default)]
+    durations: HashMap<PathBuf, (FileMeta, u64)>,
+    #[serde(default)]
+    vocab: Option<Vocab>,
+    #[serde(default)]
+    counts: Option<Counts>,
+
+    // Whether anything changed since loading, to skip rewriting an unchanged cache.
+    #[serde(skip)]
+    dirty: bool,
+    // Paths left out because JSON keys must be valid UTF-8.
+    #[serde(skip)]
+    skipped: usize,
+}
+
+// Whether a path can be written as a JSON key.
+fn cacheable(path: &Path) -> bool {
+    path.to_str().is_some()
+}
+
+impl Cache {
+    // Load the cache file, starting empty if it is missing, unreadable or was written with
+    // different settings.
+    pub fn load(path: &Path, key: &str) -> Self {
+        let mut cache = match File::open(path) {
+            Ok(file) => match serde_json::from_reader::<_, Cache>(BufReader::new(file)) {
+                Ok(cache) if cache.version == VERSION && cache.key == key => cache,
+                Ok(cache) if cache.version == VERSION => {
+                    info!("Cache {:?} is stale, rebuilding", path);
+                    Cache {
+                        phashes: cache.phashes,
+                        durations: cache.durations,
+                        ..Cache::default()
+                    }
+                }
+                Ok(_) => {
+                    info!("Cache {:?} is stale, rebuilding", path);
+                    Cache::default()
+                }
+                Err(e) => {
+                    warn!("Cache {:?}: {}", path, e);
+                    Cache::default()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Cache::default(),
+            Err(e) => {
+                warn!("Cache {:?}: {}", path, e);
+                Cache::default()
+            }
+        };
+        cache.path = Some(path.to_owned());
+        cache.version = VERSION;
+        cache.key = key.to_string();
+        info!("Cache entries: {}", cache.entries.len());
+        cache
+    }
+
+    pub fn get(&self, path: &Path, meta: &FileMeta) -> Option<&[String]> {
+        self.entries
+            .get(path)
+            .filter(|e| e.meta == *meta)
+            .map(|e| e.tokens.as_slice())
+    }
+
+    pub fn tokens(&self, path: &Path) -> Option<&[String]> {
+        self.entries.get(path).map(|e| e.tokens.as_slice())
+    }
+
+    pub fn insert(&mut self, path: &Path, meta: &FileMeta, tokens: Vec<String>) {
+        if self.path.is_none() {
+            return;
+        }
+        if !cacheable(path) {
+            self.skipped += 1;
+            return;
+        }
+        let meta = *meta;
+        self.entries.insert(path.to_owned(), Entry { meta, tokens });
+        self.dirty = true;
+        // The vocabulary was built from the old entries.
+        self.vocab = None;
+    }
+
+    // The vocabulary, if it was built with these settings.
+    pub fn vocab(&self, key: &str) -> Option<&Vocab> {
+        self.vocab.as_ref().filter(|vocab| vocab.key == key)
+    }
+
+    pub fn set_vocab(&mut self, mut vocab: Vocab) {
+        if self.path.is_none() {
+            return;
+        }
+        // Files missing from the cached ngrams are tokenized again on demand.
+        vocab.files.retain(|path, _| cacheable(path));
+        self.vocab = Some(vocab);
+        self.dirty = true;
+    }
+
+    // The counts, if they were made with these settings, taken to be updated and set again.
+    pub fn take_counts(&mut self, key: &str) -> Option<Counts> {
+        self.counts.take().filter(|counts| counts.key == key)
+    }
+
+    pub fn set_counts(&mut self, counts: Counts) {
+        // A file left out of the counted paths would be counted again as added on the next run.
+        if self.path.is_none() || !counts.paths.iter().all(|path| cacheable(path)) {
+            return;
+        }
+        self.counts = Some(counts);
+        self.dirty = true;
+    }
+
+    pub fn phash(&self, path: &Path, meta: &FileMeta) -> Option<&[u64]> {
+        self.phashes
+            .get(path)
+            .filter(|(m, _)| m == meta)
+            .map(|(_, hash)| hash.as_slice())
+    }
+
+    pub fn insert_phash(&mut self, path: &Path, meta: &FileMeta, hash: Vec<u64>) {
+        if self.path.is_none() || !cacheable(path) {
+            return;
+        }
+        self.phashes.insert(path.to_owned(), (*meta, hash));
+        self.dirty = true;
+    }
+
+    pub fn duration(&self, path: &Path, meta: &FileMeta) -> Option<u64> {
+        self.durations
+            .get(path)
+            .filter(|(m, _)| m == meta)
+            .map(|(_, duration)| *duration)
+    }
+
+    pub fn insert_duration(&mut self, path: &Path, meta: &FileMeta, duration: u64) {
+        if self.path.is_none() || !cacheable(path) {
+            return;
+        }
+        self.durations.insert(path.to_owned(), (*meta, duration));
+        self.dirty = true;
+    }
+
+    pub fn retain(&mut self, f: impl Fn(&Path) -> bool) {
+        let len = self.entries.len();
+        self.entries.retain(|path, _| f(path));
+        if self.entries.len() != len {
+            self.dirty = true;
+            self.vocab = None;
+        }
+    }
+
+    // Write the cache through a temporary file, so that an interrupted run leaves the old
+    // cache in place.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.skipped > 0 {
+            warn!(
+                "Not caching the tokens of {} files with paths that are not valid UTF-8",
+                self.skipped
+            );
+        }
+        if !self.dirty {
+            debug!("Cache {:?} is unchanged", path);
+            return Ok(());
+        }
+        let mut tmp = path.to_path_buf().into_os_string();
+        tmp.push(".tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, path)?;
+        debug!("Saved cache {:?}", path);
+        Ok(())
+    }
+}
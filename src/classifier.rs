@@ -1,16 +1,67 @@
-use crate::tokenizer::{Ngram, Tokenize, Tokenizer};
-use std::collections::HashMap;
+use crate::numeric::NumericModel;
+use crate::tokenizer::{Ngram, Tokenizer};
+use crate::Label;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// Training examples between prunes of the ngrams counted less than --prune-below.
+const PRUNE_INTERVAL: usize = 1000;
+
+const SKETCH_BITS: u32 = 16;
+const SKETCH_DEPTH: usize = 4;
+
+// Approximate counts of the ngrams pruned from an NgramCounter. Estimates are the minimum over
+// several hashed rows, overestimating by the counts of colliding ngrams.
+#[derive(Debug, Clone)]
+struct CountMinSketch {
+    rows: Vec<Vec<f64>>,
+}
+
+impl CountMinSketch {
+    fn new() -> Self {
+        Self {
+            rows: vec![vec![0.0; 1 << SKETCH_BITS]; SKETCH_DEPTH],
+        }
+    }
+
+    // Multiplicative hashing with a different seed per row.
+    fn slot(row: usize, ngram: &Ngram) -> usize {
+        let seed = (row as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let h = (ngram.id() as u64 ^ seed).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        (h >> (64 - SKETCH_BITS)) as usize
+    }
+
+    fn add(&mut self, ngram: &Ngram, count: f64) {
+        for (row, counts) in self.rows.iter_mut().enumerate() {
+            counts[Self::slot(row, ngram)] += count;
+        }
+    }
+
+    fn estimate(&self, ngram: &Ngram) -> f64 {
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(row, counts)| counts[Self::slot(row, ngram)])
+            .fold(f64::INFINITY, f64::min)
+    }
+}
 
 // The NgramCounter struct is designed to maintain counts of ngrams.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NgramCounter {
-    // A HashMap storing the counts of each ngram.
-    counts: HashMap<Ngram, usize>,
+    // A HashMap storing the counts of each ngram, fractional for down weighted examples.
+    counts: HashMap<Ngram, f64>,
+
+    // With --hash-bits, the counts summed into hashed buckets in place of the HashMap.
+    buckets: Vec<f64>,
 
     // A running total of all ngrams observed.
-    total: usize,
+    total: f64,
 
     unique_ngram_count: u32,
+
+    // The counts of pruned ngrams, once any were pruned.
+    sketch: Option<CountMinSketch>,
 }
 
 impl NgramCounter {
@@ -20,32 +71,151 @@ impl NgramCounter {
 
         Self {
             counts: HashMap::new(),
-            total: 0,
+            buckets: Vec::new(),
+            total: 0.0,
             unique_ngram_count,
+            sketch: None,
+        }
+    }
+
+    // Increment the count for a given ngram by the weight of the training example.
+    fn inc(&mut self, ngram: Ngram, weight: f64) {
+        if self.buckets.is_empty() {
+            let e = self.counts.entry(ngram).or_default();
+            *e += weight;
+        } else {
+            let bucket = self.bucket(&ngram);
+            self.buckets[bucket] += weight;
+        }
+        self.total += weight;
+    }
+
+    // Count ngrams in 2^bits buckets, shared by the ngrams hashing to the same bucket.
+    fn set_hash_bits(&mut self, bits: u32) {
+        assert!(self.total == 0.0);
+        self.buckets = vec![0.0; 1 << bits];
+        self.unique_ngram_count = self.unique_ngram_count.min(1 << bits);
+    }
+
+    fn bucket(&self, ngram: &Ngram) -> usize {
+        let bits = self.buckets.len().trailing_zeros();
+        ((ngram.id() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - bits)) as usize
+    }
+
+    fn count(&self, ngram: &Ngram) -> f64 {
+        if !self.buckets.is_empty() {
+            return self.buckets[self.bucket(ngram)];
         }
+        let pruned = self.sketch.as_ref().map_or(0.0, |s| s.estimate(ngram));
+        self.counts.get(ngram).cloned().unwrap_or_default() + pruned
     }
 
-    // Increment the count for a given ngram.
-    fn inc(&mut self, ngram: Ngram) {
-        let e = self.counts.entry(ngram).or_default();
-        *e += 1;
-        self.total += 1;
+    // Move the count of an ngram into the sketch.
+    fn prune(&mut self, ngram: &Ngram) {
+        if let Some(count) = self.counts.remove(ngram) {
+            self.sketch
+                .get_or_insert_with(CountMinSketch::new)
+                .add(ngram, count);
+        }
+    }
+
+    // The Laplace smoothed denominator, shared by every ngram.
+    fn norm(&self) -> f64 {
+        self.total + self.unique_ngram_count as f64
     }
 
     // Get the smoothed log probability of observing a given ngram.
     //
     // Laplace smoothed.
     fn log_p(&self, ngram: &Ngram) -> f64 {
-        let count = (self.counts.get(ngram).cloned().unwrap_or_default() + 1) as f64;
-        let total = (self.total + self.unique_ngram_count as usize) as f64;
-        (count / total).max(f64::MIN_POSITIVE).ln()
+        let count = self.count(ngram) + 1.0;
+        (count / self.norm()).max(f64::MIN_POSITIVE).ln()
+    }
+}
+
+// Delete and keep counts per directory, for a prior conditioned on the directory of a file.
+#[derive(Debug, Clone)]
+pub struct DirPriors {
+    roots: Vec<PathBuf>,
+    // Directories below the root that distinguish files.
+    depth: usize,
+    // Pseudo counts at the overall delete rate added to each directory.
+    strength: f64,
+    counts: HashMap<PathBuf, (usize, usize)>,
+    delete: usize,
+    keep: usize,
+}
+
+impl DirPriors {
+    pub fn new(roots: &[PathBuf], depth: usize, strength: f64) -> Self {
+        Self {
+            // As the directory walk names them.
+            roots: roots
+                .iter()
+                .map(|root| crate::walk::normalize(root))
+                .collect(),
+            depth,
+            strength,
+            counts: HashMap::new(),
+            delete: 0,
+            keep: 0,
+        }
+    }
+
+    // The root a file was found under, with up to depth directories below it.
+    fn dir(&self, path: &Path) -> Option<PathBuf> {
+        let root = self.roots.iter().find(|root| path.starts_with(root))?;
+        let rest = path.strip_prefix(root).ok()?.parent()?;
+        Some(root.join(rest.iter().take(self.depth).collect::<PathBuf>()))
+    }
+
+    fn train(&mut self, label: Label, path: &Path) {
+        let counts = self
+            .dir(path)
+            .map(|dir| self.counts.entry(dir).or_default());
+        match label {
+            Label::Delete => {
+                self.delete += 1;
+                if let Some(counts) = counts {
+                    counts.0 += 1;
+                }
+            }
+            Label::Keep => {
+                self.keep += 1;
+                if let Some(counts) = counts {
+                    counts.1 += 1;
+                }
+            }
+        }
+    }
+
+    // The delete log odds of the directory relative to the overall delete log odds. Directory
+    // rates are shrunk towards the overall rate, so sparsely labeled directories stay near 0.
+    fn log_odds(&self, path: &Path) -> f64 {
+        let Some((delete, keep)) = self.dir(path).and_then(|dir| self.counts.get(&dir)) else {
+            return 0.0;
+        };
+        let overall = (self.delete + 1) as f64 / (self.delete + self.keep + 2) as f64;
+        let dir =
+            (*delete as f64 + self.strength * overall) / ((delete + keep) as f64 + self.strength);
+        let logit = |p: f64| p.ln() - (1.0 - p).ln();
+        logit(dir) - logit(overall)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NaiveBayesClassifier {
     delete: NgramCounter,
     keep: NgramCounter,
+    dir_priors: Option<DirPriors>,
+    numeric: Option<NumericModel>,
+    // Per ngram weights of the log odds, all ngrams weigh 1.0 when unset.
+    weights: Option<Vec<f64>>,
+    // See --prune-below and --max-model-ngrams.
+    prune_below: Option<f64>,
+    max_ngrams: Option<usize>,
+    examples: usize,
+    prunes: usize,
 }
 
 impl NaiveBayesClassifier {
@@ -53,56 +223,199 @@ impl NaiveBayesClassifier {
         Self {
             delete: NgramCounter::new(tokenizer),
             keep: NgramCounter::new(tokenizer),
+            dir_priors: None,
+            numeric: None,
+            weights: tokenizer.ngram_idf.clone(),
+            prune_below: None,
+            max_ngrams: None,
+            examples: 0,
+            prunes: 0,
         }
     }
 
-    pub fn train_delete(&mut self, ngrams: &[Ngram]) {
-        for ngram in ngrams {
-            self.delete.inc(*ngram);
+    pub fn weight(&self, ngram: &Ngram) -> f64 {
+        match &self.weights {
+            Some(weights) => weights.get(ngram.id()).cloned().unwrap_or_default(),
+            None => 1.0,
         }
     }
 
-    pub fn train_keep(&mut self, ngrams: &[Ngram]) {
+    pub fn set_dir_priors(&mut self, dir_priors: DirPriors) {
+        self.dir_priors = Some(dir_priors);
+    }
+
+    pub fn set_numeric(&mut self, numeric: NumericModel) {
+        self.numeric = Some(numeric);
+    }
+
+    // Count ngrams in 2^bits hashed buckets instead of exactly, see --hash-bits.
+    pub fn set_hash_bits(&mut self, bits: u32) {
+        self.delete.set_hash_bits(bits);
+        self.keep.set_hash_bits(bits);
+    }
+
+    // Whether ngrams share hashed buckets, so training one changes the counts of others.
+    pub fn hashed(&self) -> bool {
+        !self.delete.buckets.is_empty()
+    }
+
+    pub fn set_pruning(&mut self, prune_below: Option<f64>, max_ngrams: Option<usize>) {
+        self.prune_below = prune_below;
+        self.max_ngrams = max_ngrams;
+    }
+
+    // The number of prunes so far, which change the counts of many ngrams at once.
+    pub fn prunes(&self) -> usize {
+        self.prunes
+    }
+
+    // Whether scores depend on file metadata as well as ngrams.
+    pub fn has_meta(&self) -> bool {
+        self.dir_priors.is_some() || self.numeric.is_some()
+    }
+
+    // Count a labeled file towards the prior of its directory, with --dir-prior-depth, and the
+    // bins of its numeric features, with --learn-numeric.
+    pub fn train_meta(&mut self, label: Label, path: &Path) {
+        if let Some(dir_priors) = &mut self.dir_priors {
+            dir_priors.train(label, path);
+        }
+        if let Some(numeric) = &mut self.numeric {
+            numeric.train(label, path);
+        }
+    }
+
+    // The delete log odds of a file from its directory and numeric features, 0 without
+    // --dir-prior-depth and --learn-numeric.
+    pub fn meta_log_odds(&self, path: &Path) -> f64 {
+        self.dir_priors.as_ref().map_or(0.0, |d| d.log_odds(path))
+            + self.numeric.as_ref().map_or(0.0, |n| n.log_odds(path))
+    }
+
+    pub fn train(&mut self, label: Label, ngrams: &[Ngram]) {
+        self.train_weighted(label, ngrams, 1.0);
+    }
+
+    // Train on an example counting for less than a full one, see --label-half-life.
+    pub fn train_weighted(&mut self, label: Label, ngrams: &[Ngram], weight: f64) {
+        let counter = match label {
+            Label::Delete => &mut self.delete,
+            Label::Keep => &mut self.keep,
+        };
         for ngram in ngrams {
-            self.keep.inc(*ngram);
+            counter.inc(*ngram, weight);
+        }
+
+        self.examples += 1;
+        let periodic = self.prune_below.is_some() && self.examples.is_multiple_of(PRUNE_INTERVAL);
+        let entries = self.delete.counts.len() + self.keep.counts.len();
+        if periodic || self.max_ngrams.is_some_and(|max| entries > max) {
+            self.prune();
         }
     }
 
+    // Move the ngrams counted less than --prune-below, and the least counted ngrams beyond
+    // --max-model-ngrams, out of the exact counts into count-min sketches.
+    fn prune(&mut self) {
+        let mut totals: HashMap<Ngram, (f64, usize)> = HashMap::new();
+        for (ngram, count) in self.delete.counts.iter().chain(&self.keep.counts) {
+            let e = totals.entry(*ngram).or_default();
+            e.0 += count;
+            e.1 += 1;
+        }
+        let mut totals: Vec<(f64, usize, Ngram)> = totals
+            .into_iter()
+            .map(|(ngram, (total, entries))| (total, entries, ngram))
+            .collect();
+        totals.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+
+        let mut entries: usize = totals.iter().map(|(_, entries, _)| entries).sum();
+        // Pruned to 90% of the cap so that it is not pruned again on the next example.
+        let target = self.max_ngrams.map_or(usize::MAX, |max| max / 10 * 9);
+        let mut pruned = HashSet::new();
+        for (total, n, ngram) in totals {
+            if entries <= target && self.prune_below.is_none_or(|min| total >= min) {
+                break;
+            }
+            entries -= n;
+            pruned.insert(ngram);
+        }
+
+        for ngram in &pruned {
+            self.delete.prune(ngram);
+            self.keep.prune(ngram);
+        }
+        self.prunes += 1;
+        log::debug!("Pruned {} ngrams", pruned.len());
+    }
+
+    // The delete log odds of an ngram split into a part that only changes when this ngram is
+    // trained, and a normalization part shared by all ngrams (see norm_log_odds). Scores can
+    // then be updated incrementally for the ngrams of each new training example.
+    pub fn ngram_log_odds(&self, ngram: &Ngram) -> f64 {
+        let delete = self.delete.count(ngram) + 1.0;
+        let keep = self.keep.count(ngram) + 1.0;
+        delete.ln() - keep.ln()
+    }
+
+    pub fn norm_log_odds(&self) -> f64 {
+        self.keep.norm().ln() - self.delete.norm().ln()
+    }
+
+    // The weighted delete log odds of a file.
     pub fn predict_delete(&self, ngrams: &[Ngram]) -> f64 {
-        let mut log_p = 0.0;
-        for ngram in ngrams {
-            log_p += self.delete.log_p(ngram);
-            log_p -= self.keep.log_p(ngram);
+        let norm_log_odds = self.norm_log_odds();
+        ngrams
+            .iter()
+            .map(|ngram| self.weight(ngram) * (self.ngram_log_odds(ngram) + norm_log_odds))
+            .sum()
+    }
+
+    // The delete log odds of a file from both its ngrams and its metadata.
+    pub fn predict(&self, path: &Path, ngrams: &[Ngram]) -> f64 {
+        self.predict_delete(ngrams) + self.meta_log_odds(path)
+    }
+
+    // The weighted delete log odds of an ngram.
+    pub fn log_odds(&self, ngram: &Ngram) -> f64 {
+        self.weight(ngram) * (self.delete.log_p(ngram) - self.keep.log_p(ngram))
+    }
+
+    // The number of distinct ngrams seen in training, or of buckets with --hash-bits.
+    pub fn vocab_size(&self) -> usize {
+        if !self.delete.buckets.is_empty() {
+            return self
+                .delete
+                .buckets
+                .iter()
+                .zip(&self.keep.buckets)
+                .filter(|(delete, keep)| **delete != 0.0 || **keep != 0.0)
+                .count();
         }
-        log_p
+        let keep_only = self
+            .keep
+            .counts
+            .keys()
+            .filter(|ngram| !self.delete.counts.contains_key(ngram))
+            .count();
+        self.delete.counts.len() + keep_only
+    }
+
+    // The (delete, keep) training counts of an ngram.
+    pub fn counts(&self, ngram: &Ngram) -> (f64, f64) {
+        (self.delete.count(ngram), self.keep.count(ngram))
     }
 
     pub fn debug_delete(&self, tokenizer: &Tokenizer, ngrams: &[Ngram]) -> Vec<(f64, String)> {
         let mut scores: Vec<(f64, String)> = Vec::new();
 
         for ngram in ngrams {
-            let score = self.delete.log_p(ngram) - self.keep.log_p(ngram);
-
-            if let Some(tokens) = tokenizer.ngram_tokens.get(ngram) {
-                let mut v = Vec::new();
-                for token in tokens {
-                    if let Some(s) = tokenizer.token_string.get(token) {
-                        v.push(s.to_string());
-                    } else {
-                        v.push(String::from("*"));
-                    }
-                }
-
-                let k = match tokenizer.tokenize {
-                    Tokenize::Chars => v.join(""),
-                    Tokenize::Words => v.join(" "),
-                };
-
-                scores.push((score, k));
+            if let Some(k) = tokenizer.ngram_string(ngram) {
+                scores.push((self.log_odds(ngram), k));
             }
         }
 
-        scores.sort_by(|a, b| a.partial_cmp(&b).unwrap());
+        scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
         for (k, _) in scores.iter_mut() {
             *k = crate::round(*k);
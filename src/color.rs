@@ -0,0 +1,68 @@
+use clap::ValueEnum;
+use nu_ansi_term::{Color, Style};
+use std::fmt::Display;
+use std::io::IsTerminal;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is unset.
+    Auto,
+    Always,
+    Never,
+}
+
+// Styles terminal output, or passes it through unchanged when color is disabled.
+#[derive(Debug, Clone, Copy)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    pub fn new(choice: ColorChoice) -> Self {
+        let enabled = match choice {
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        Self { enabled }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn paint(&self, style: Style, s: impl Display) -> String {
+        if self.enabled {
+            style.paint(s.to_string()).to_string()
+        } else {
+            s.to_string()
+        }
+    }
+
+    // Text for a score leaning towards delete is red and towards keep green.
+    pub fn paint_sign(&self, score: f64, s: impl Display) -> String {
+        let style = if score > 0.0 {
+            Color::Red.normal()
+        } else if score < 0.0 {
+            Color::Green.normal()
+        } else {
+            Style::new()
+        };
+        self.paint(style, s)
+    }
+
+    pub fn score(&self, score: f64) -> String {
+        self.paint_sign(score, format!("{:?}", score))
+    }
+
+    pub fn name(&self, s: impl Display) -> String {
+        self.paint(Color::Cyan.normal(), s)
+    }
+
+    pub fn path(&self, s: impl Display) -> String {
+        self.paint(Style::new().bold(), s)
+    }
+}
@@ -0,0 +1,140 @@
+use crate::Args;
+use clap::CommandFactory;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+
+// ~/.config/classi-cine/config.toml, or under $XDG_CONFIG_HOME when set.
+fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("classi-cine").join("config.toml"))
+}
+
+fn all_longs(command: &clap::Command, longs: &mut HashSet<String>) {
+    for arg in command.get_arguments() {
+        if let Some(long) = arg.get_long() {
+            longs.insert(long.to_string());
+        }
+    }
+    for sub in command.get_subcommands() {
+        all_longs(sub, longs);
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// The command line with the options from the config file inserted after the subcommand, so
+// that the options given on the command line take precedence.
+//
+// The config file is a flat TOML table of long option names to values, for example:
+//
+//     tokenize = "words"
+//     windows = 3
+//     fullscreen = true
+//     video_exts = ["mkv", "mp4"]
+//
+// Options that the invoked command does not take are ignored.
+pub fn args() -> io::Result<Vec<OsString>> {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+
+    let explicit = argv.iter().enumerate().find_map(|(i, arg)| {
+        let arg = arg.to_str()?;
+        if arg == "--config" {
+            argv.get(i + 1).map(PathBuf::from)
+        } else {
+            arg.strip_prefix("--config=").map(PathBuf::from)
+        }
+    });
+    let path = match explicit {
+        Some(path) => path,
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(argv),
+        },
+    };
+
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+
+    // The innermost subcommand on the command line and where its arguments start.
+    let root = Args::command();
+    let mut command = &root;
+    let mut insert_at = 1;
+    for (i, arg) in argv.iter().enumerate().skip(1) {
+        if let Some(sub) = arg.to_str().and_then(|arg| command.find_subcommand(arg)) {
+            command = sub;
+            insert_at = i + 1;
+        }
+    }
+
+    let mut known = HashSet::new();
+    all_longs(&root, &mut known);
+    let accepted: HashMap<&str, Option<char>> = command
+        .get_arguments()
+        .filter_map(|arg| Some((arg.get_long()?, arg.get_short())))
+        .collect();
+
+    let given = |long: &str| {
+        let short = accepted
+            .get(long)
+            .cloned()
+            .flatten()
+            .map(|c| format!("-{}", c));
+        argv.iter().filter_map(|arg| arg.to_str()).any(|arg| {
+            Some(arg) == short.as_deref()
+                || arg
+                    .strip_prefix("--")
+                    .is_some_and(|arg| arg == long || arg.starts_with(&format!("{}=", long)))
+        })
+    };
+
+    let mut inserted: Vec<OsString> = Vec::new();
+    for (key, value) in &table {
+        let long = key.replace('_', "-");
+        if !known.contains(&long) {
+            return Err(invalid(format!(
+                "{}: unknown option {:?}",
+                path.display(),
+                key
+            )));
+        }
+        if !accepted.contains_key(long.as_str()) || long == "config" || given(&long) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        for value in values {
+            let flag = OsString::from(format!("--{}", long));
+            match value {
+                toml::Value::Boolean(true) => inserted.push(flag),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(s) => inserted.extend([flag, s.into()]),
+                toml::Value::Integer(i) => inserted.extend([flag, i.to_string().into()]),
+                toml::Value::Float(f) => inserted.extend([flag, f.to_string().into()]),
+                value => {
+                    return Err(invalid(format!(
+                        "{}: unsupported value for {:?}: {}",
+                        path.display(),
+                        key,
+                        value
+                    )))
+                }
+            }
+        }
+    }
+
+    argv.splice(insert_at..insert_at, inserted);
+    Ok(argv)
+}
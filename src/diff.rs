@@ -0,0 +1,99 @@
+use crate::{CommonArgs, Label, Model, State};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// The --delete and --keep files of the first label set.
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The text file containing the files to delete in the second label set.
+    #[clap(long)]
+    other_delete: PathBuf,
+
+    /// The text file containing the files to keep in the second label set.
+    #[clap(long)]
+    other_keep: PathBuf,
+
+    /// The number of candidate files with the largest score change to list.
+    #[clap(long, default_value = "20")]
+    top: usize,
+}
+
+fn labels(delete: &State, keep: &State) -> BTreeMap<PathBuf, Label> {
+    delete
+        .iter()
+        .map(|path| (path, Label::Delete))
+        .chain(keep.iter().map(|path| (path, Label::Keep)))
+        .collect()
+}
+
+pub fn run(args: &DiffArgs) -> io::Result<()> {
+    let model = Model::load(&args.common)?;
+    let tokenizer = &model.tokenizer;
+
+    let other_delete = State::load_args(&args.other_delete, &args.common)?;
+    let other_keep = State::load_args(&args.other_keep, &args.common)?;
+
+    let a = labels(&model.delete, &model.keep);
+    let b = labels(&other_delete, &other_keep);
+    let now = crate::now_secs();
+
+    println!("Labeled differently");
+    for (path, label) in &a {
+        if let Some(other) = b.get(path).filter(|other| *other != label) {
+            println!("{:>6} {:>6}  {:?}", label.as_str(), other.as_str(), path);
+        }
+    }
+
+    for (title, this, other) in [("Only in first", &a, &b), ("Only in second", &b, &a)] {
+        println!();
+        println!("{}", title);
+        for (path, label) in this.iter().filter(|(p, _)| !other.contains_key(*p)) {
+            println!("{:>6}  {:?}", label.as_str(), path);
+        }
+    }
+
+    let mut other_classifier = model.untrained();
+    for (path, label) in &b {
+        let state = match label {
+            Label::Delete => &other_delete,
+            Label::Keep => &other_keep,
+        };
+        let weight = state.weight(path, args.common.label_half_life, now);
+        other_classifier.train_weighted(*label, &tokenizer.ngrams_cached(path), weight);
+        other_classifier.train_meta(*label, path);
+    }
+
+    // Candidates are the files unlabeled in both sets.
+    let mut changes: Vec<(f64, f64, &PathBuf)> = model
+        .files
+        .keys()
+        .filter(|path| !b.contains_key(*path))
+        .map(|path| {
+            let ngrams = tokenizer.ngrams_cached(path);
+            (
+                model.classifier.predict(path, &ngrams),
+                other_classifier.predict(path, &ngrams),
+                path,
+            )
+        })
+        .collect();
+    changes.sort_by(|x, y| {
+        (y.1 - y.0)
+            .abs()
+            .total_cmp(&(x.1 - x.0).abs())
+            .then(x.2.cmp(y.2))
+    });
+
+    println!();
+    println!("Largest score changes");
+    println!("{:>9} {:>9} {:>9}  path", "first", "second", "change");
+    for (a, b, path) in changes.iter().take(args.top) {
+        println!("{:>9.3} {:>9.3} {:>+9.3}  {:?}", a, b, b - a, path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use crate::cache::Cache;
+use crate::tokenizer::Tokenizer;
+use crate::walk::FileMeta;
+use crate::{phash, CommonArgs, Label, State};
+use humansize::{format_size, BINARY};
+use log::*;
+use rayon::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Bytes hashed from each end of a file.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DupesArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Also require matching durations, as reported by ffprobe.
+    #[clap(long)]
+    duration: bool,
+
+    /// Copy the label of an already classified file to its unclassified duplicates.
+    #[clap(long)]
+    inherit: bool,
+
+    /// Find near-duplicates such as re-encodes by comparing perceptual hashes of sampled
+    /// frames, decoded with ffmpeg and kept in the --cache file, instead of file contents.
+    #[clap(long)]
+    phash: bool,
+
+    /// The fraction of matching pHash bits for two files to be near-duplicates.
+    #[clap(long, default_value = "0.9")]
+    phash_threshold: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    size: u64,
+    hash: u64,
+    duration: Option<u64>,
+}
+
+// Hash the size and the first and last SAMPLE_SIZE bytes of a file.
+fn partial_hash(path: &Path, size: u64) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    size.hash(&mut hasher);
+
+    let mut file = File::open(path)?;
+    let mut buf = Vec::with_capacity(SAMPLE_SIZE as usize);
+    (&mut file).take(SAMPLE_SIZE).read_to_end(&mut buf)?;
+    buf.hash(&mut hasher);
+
+    if size > SAMPLE_SIZE {
+        buf.clear();
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(SAMPLE_SIZE).max(SAMPLE_SIZE),
+        ))?;
+        file.take(SAMPLE_SIZE).read_to_end(&mut buf)?;
+        buf.hash(&mut hasher);
+    }
+
+    Ok(hasher.finish())
+}
+
+// Duration in whole seconds.
+pub fn probe_duration(path: &Path) -> Option<u64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+    let duration: f64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    Some(duration.round() as u64)
+}
+
+// The pool ffprobe and ffmpeg run on, bounded by --probe-jobs. The threads mostly wait on the
+// child processes, so on slow storage more jobs than CPUs can pay off.
+pub fn probe_pool(common: &CommonArgs) -> io::Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(common.probe_jobs.unwrap_or(0))
+        .thread_name(|i| format!("probe-{}", i))
+        .build()
+        .map_err(io::Error::other)
+}
+
+// The durations of the given files, probed when they are not in the --cache file. Files
+// ffprobe cannot read are left out.
+pub fn durations(common: &CommonArgs, paths: &[PathBuf]) -> io::Result<HashMap<PathBuf, u64>> {
+    let mut cache = match &common.cache {
+        Some(path) => Cache::load(path, &Tokenizer::cache_key(common)),
+        None => Cache::default(),
+    };
+
+    let mut ret = HashMap::new();
+    let mut missing = Vec::new();
+    for path in paths {
+        let Ok(meta) = std::fs::metadata(path) else {
+            continue;
+        };
+        let meta = FileMeta::new(&meta);
+        match cache.duration(path, &meta) {
+            Some(duration) => {
+                ret.insert(path.clone(), duration);
+            }
+            None => missing.push((path, meta)),
+        }
+    }
+    info!("Probing {} of {} files", missing.len(), paths.len());
+
+    let probed: Vec<(&PathBuf, FileMeta, u64)> = probe_pool(common)?.install(|| {
+        missing
+            .into_par_iter()
+            .filter_map(|(path, meta)| Some((path, meta, probe_duration(path)?)))
+            .collect()
+    });
+    for (path, meta, duration) in probed {
+        cache.insert_duration(path, &meta, duration);
+        ret.insert(path.clone(), duration);
+    }
+    cache.save()?;
+    Ok(ret)
+}
+
+// Files with identical sizes and partial content hashes, largest first.
+fn exact_clusters(
+    args: &DupesArgs,
+    files: HashMap<PathBuf, FileMeta>,
+) -> io::Result<Vec<(String, BTreeSet<PathBuf>)>> {
+    // Only files sharing a size can be duplicates, so group on size before reading any content.
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for (path, meta) in files {
+        if meta.size > 0 {
+            by_size.entry(meta.size).or_default().push(path);
+        }
+    }
+    let candidates: Vec<(PathBuf, u64)> = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (path, size)))
+        .collect();
+
+    info!("Hashing {} candidate files", candidates.len());
+
+    let durations = if args.duration {
+        let paths: Vec<PathBuf> = candidates.iter().map(|(path, _)| path.clone()).collect();
+        durations(&args.common, &paths)?
+    } else {
+        HashMap::new()
+    };
+
+    let keyed: Vec<(Key, PathBuf)> = candidates
+        .into_par_iter()
+        .filter_map(|(path, size)| {
+            let hash = match partial_hash(&path, size) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    warn!("Hash {:?}: {}", path, e);
+                    return None;
+                }
+            };
+            let duration = durations.get(&path).copied();
+            Some((
+                Key {
+                    size,
+                    hash,
+                    duration,
+                },
+                path,
+            ))
+        })
+        .collect();
+
+    let mut clusters: HashMap<Key, BTreeSet<PathBuf>> = HashMap::new();
+    for (key, path) in keyed {
+        clusters.entry(key).or_default().insert(path);
+    }
+    let mut clusters: Vec<(Key, BTreeSet<PathBuf>)> = clusters
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect();
+    clusters.sort_by(|a, b| b.0.size.cmp(&a.0.size).then_with(|| a.1.cmp(&b.1)));
+    Ok(clusters
+        .into_iter()
+        .map(|(key, paths)| {
+            let header = format!("{} x {}", paths.len(), format_size(key.size, BINARY));
+            (header, paths)
+        })
+        .collect())
+}
+
+// Files linked by pHash similarity at or above the threshold, largest clusters first.
+fn phash_clusters(
+    args: &DupesArgs,
+    paths: Vec<PathBuf>,
+) -> io::Result<Vec<(String, BTreeSet<PathBuf>)>> {
+    let hashes: Vec<(PathBuf, Vec<u64>)> =
+        phash::hashes(&args.common, &paths)?.into_iter().collect();
+
+    // Union-find over the similar pairs.
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    let pairs: Vec<(usize, usize)> = (0..hashes.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let hashes = &hashes;
+            (i + 1..hashes.len())
+                .filter(move |j| {
+                    phash::similarity(&hashes[i].1, &hashes[*j].1) >= args.phash_threshold
+                })
+                .map(move |j| (i, j))
+        })
+        .collect();
+    for (i, j) in pairs {
+        let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+        parent[a] = b;
+    }
+
+    let mut clusters: HashMap<usize, BTreeSet<PathBuf>> = HashMap::new();
+    for (i, (path, _)) in hashes.iter().enumerate() {
+        let root = find(&mut parent, i);
+        clusters.entry(root).or_default().insert(path.clone());
+    }
+    let mut clusters: Vec<BTreeSet<PathBuf>> = clusters
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect();
+    clusters.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    Ok(clusters
+        .into_iter()
+        .map(|paths| (format!("{} similar", paths.len()), paths))
+        .collect())
+}
+
+pub fn run(args: &DupesArgs) -> io::Result<()> {
+    let common = &args.common;
+
+    let files = crate::collect_files(common)?;
+
+    let mut delete = State::load_args(&common.delete, common)?;
+    let mut keep = State::load_args(&common.keep, common)?;
+    let mut labels: HashMap<PathBuf, Label> = HashMap::new();
+    for path in delete.iter() {
+        labels.insert(path, Label::Delete);
+    }
+    for path in keep.iter() {
+        labels.insert(path, Label::Keep);
+    }
+
+    let clusters = if args.phash {
+        phash_clusters(args, files.into_keys().collect())?
+    } else {
+        exact_clusters(args, files)?
+    };
+
+    let mut inherited = 0;
+    for (i, (header, paths)) in clusters.iter().enumerate() {
+        println!("Cluster {}: {}", i + 1, header);
+
+        let mut cluster_labels: BTreeSet<Label> = BTreeSet::new();
+        for path in paths {
+            let label = labels.get(path);
+            let tag = label.map(Label::as_str).unwrap_or_default();
+            println!("  {:<6} {}", tag, path.display());
+            cluster_labels.extend(label);
+        }
+
+        if !args.inherit {
+            continue;
+        }
+        if cluster_labels.len() > 1 {
+            warn!("Cluster {} has conflicting labels, not inheriting", i + 1);
+            continue;
+        }
+        if let Some(label) = cluster_labels.into_iter().next() {
+            let state = match label {
+                Label::Delete => &mut delete,
+                Label::Keep => &mut keep,
+            };
+            for path in paths.iter().filter(|path| !labels.contains_key(*path)) {
+                state.update(&path.to_string_lossy())?;
+                inherited += 1;
+            }
+        }
+    }
+
+    info!("Duplicate clusters: {}", clusters.len());
+    if args.inherit {
+        info!("Inherited labels: {}", inherited);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::tokenizer::Ngram;
+use crate::{CommonArgs, Label, Model};
+use log::*;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct EvaluateArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The number of folds, each fold is held out once while training on the others.
+    #[clap(long, default_value = "5")]
+    folds: usize,
+}
+
+// Confusion matrix with delete as the positive label.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Metrics {
+    tp: usize,
+    fp: usize,
+    tn: usize,
+    fn_: usize,
+}
+
+impl Metrics {
+    pub fn add(&mut self, label: Label, log_odds: f64) {
+        match (label, log_odds > 0.0) {
+            (Label::Delete, true) => self.tp += 1,
+            (Label::Delete, false) => self.fn_ += 1,
+            (Label::Keep, true) => self.fp += 1,
+            (Label::Keep, false) => self.tn += 1,
+        }
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.tp + self.tn, self.tp + self.tn + self.fp + self.fn_)
+    }
+
+    pub fn precision(&self) -> f64 {
+        ratio(self.tp, self.tp + self.fp)
+    }
+
+    pub fn recall(&self) -> f64 {
+        ratio(self.tp, self.tp + self.fn_)
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+// Area under the ROC curve for delete scores, from the Mann-Whitney U statistic with tied
+// scores given their average rank.
+pub fn auc(scores: &[(Label, f64)]) -> f64 {
+    let mut scores = scores.to_vec();
+    scores.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut rank_sum = 0.0;
+    let mut i = 0;
+    while i < scores.len() {
+        let mut j = i;
+        while j < scores.len() && scores[j].1 == scores[i].1 {
+            j += 1;
+        }
+        // Ranks i+1..=j averaged.
+        let rank = (i + 1 + j) as f64 / 2.0;
+        rank_sum += rank * scores[i..j].iter().filter(|s| s.0 == Label::Delete).count() as f64;
+        i = j;
+    }
+
+    let p = scores.iter().filter(|s| s.0 == Label::Delete).count() as f64;
+    let n = scores.len() as f64 - p;
+    if p == 0.0 || n == 0.0 {
+        return 0.5;
+    }
+    (rank_sum - p * (p + 1.0) / 2.0) / (p * n)
+}
+
+pub fn run(args: &EvaluateArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+    let tokenizer = &model.tokenizer;
+
+    let mut examples: Vec<(u64, PathBuf, Label)> = model
+        .delete
+        .iter()
+        .map(|path| (path, Label::Delete))
+        .chain(model.keep.iter().map(|path| (path, Label::Keep)))
+        .map(|(path, label)| (crate::seeded_hash(common.seed, &path), path, label))
+        .collect();
+    examples.sort();
+
+    let folds = args.folds.min(examples.len());
+    if folds < 2 {
+        error!("Need at least 2 labeled files and folds to evaluate");
+        return Ok(());
+    }
+
+    let now = crate::now_secs();
+    let examples: Vec<(Label, PathBuf, Vec<Ngram>, f64)> = examples
+        .into_iter()
+        .map(|(_, path, label)| {
+            let state = match label {
+                Label::Delete => &model.delete,
+                Label::Keep => &model.keep,
+            };
+            let weight = state.weight(&path, common.label_half_life, now);
+            let ngrams = tokenizer.ngrams_cached(&path);
+            (label, path, ngrams, weight)
+        })
+        .collect();
+
+    let mut total = Metrics::default();
+    let mut scores: Vec<(Label, f64)> = Vec::new();
+
+    println!(
+        "{:>4} {:>6} {:>8} {:>9} {:>6} {:>6}",
+        "fold", "count", "accuracy", "precision", "recall", "auc"
+    );
+    for fold in 0..folds {
+        let mut classifier = model.untrained();
+        for (_, (label, path, ngrams, weight)) in examples
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % folds != fold)
+        {
+            classifier.train_weighted(*label, ngrams, *weight);
+            classifier.train_meta(*label, path);
+        }
+
+        let mut metrics = Metrics::default();
+        let mut fold_scores = Vec::new();
+        for (_, (label, path, ngrams, _)) in examples
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % folds == fold)
+        {
+            let log_odds = classifier.predict(path, ngrams);
+            metrics.add(*label, log_odds);
+            total.add(*label, log_odds);
+            fold_scores.push((*label, log_odds));
+        }
+
+        println!(
+            "{:>4} {:>6} {:>8.3} {:>9.3} {:>6.3} {:>6.3}",
+            fold + 1,
+            fold_scores.len(),
+            metrics.accuracy(),
+            metrics.precision(),
+            metrics.recall(),
+            auc(&fold_scores)
+        );
+        scores.extend(fold_scores);
+    }
+
+    println!(
+        "{:>4} {:>6} {:>8.3} {:>9.3} {:>6.3} {:>6.3}",
+        "all",
+        scores.len(),
+        total.accuracy(),
+        total.precision(),
+        total.recall(),
+        auc(&scores)
+    );
+
+    Ok(())
+}
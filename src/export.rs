@@ -0,0 +1,147 @@
+use crate::tokenizer::Ngram;
+use crate::{round, CommonArgs, Label, Model};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Tsv,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    #[clap(long, default_value = "csv")]
+    format: Format,
+
+    /// Write to this file instead of stdout.
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum FeatureFormat {
+    /// "label index:count ..." lines with 1-based ngram ids, 1 for delete and 0 for keep.
+    Libsvm,
+    /// path,label,ngram,count rows, one per distinct ngram of a file.
+    Csv,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExportFeaturesArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    #[clap(long, default_value = "libsvm")]
+    format: FeatureFormat,
+
+    /// Write to this file instead of stdout.
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+fn writer(output: &Option<PathBuf>) -> io::Result<Box<dyn Write>> {
+    Ok(match output {
+        Some(path) => Box::new(io::BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    })
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+// Write a path,label,timestamp,score row for every labeled file, with the score of the model
+// trained on all labeled files. The timestamp, in seconds since the unix epoch, is empty for
+// files labeled before timestamps were recorded.
+pub fn run(args: &ExportArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+
+    let mut out = writer(&args.output)?;
+
+    let sep = match args.format {
+        Format::Csv => ",",
+        Format::Tsv => "\t",
+    };
+    writeln!(out, "{}", ["path", "label", "timestamp", "score"].join(sep))?;
+    for (label, state) in [(Label::Delete, &model.delete), (Label::Keep, &model.keep)] {
+        for path in state.iter() {
+            let score = model.score(&path);
+            let timestamp = state
+                .timestamp(&path)
+                .map(|t| t.to_string())
+                .unwrap_or_default();
+            let path = path.to_string_lossy();
+            let path = match args.format {
+                Format::Csv => quote(&path),
+                Format::Tsv => path.to_string(),
+            };
+            writeln!(
+                out,
+                "{}",
+                [
+                    path,
+                    label.as_str().to_string(),
+                    timestamp,
+                    round(score).to_string()
+                ]
+                .join(sep)
+            )?;
+        }
+    }
+    out.flush()
+}
+
+// Write the ngram counts of every labeled file, the features the classifier is trained on.
+pub fn run_features(args: &ExportFeaturesArgs) -> io::Result<()> {
+    let model = Model::load(&args.common)?;
+    let tokenizer = &model.tokenizer;
+
+    let mut out = writer(&args.output)?;
+    if args.format == FeatureFormat::Csv {
+        writeln!(out, "path,label,ngram,count")?;
+    }
+    for (label, state) in [(Label::Delete, &model.delete), (Label::Keep, &model.keep)] {
+        for path in state.iter() {
+            let mut counts: BTreeMap<Ngram, u32> = BTreeMap::new();
+            for ngram in tokenizer.ngrams_cached(&path) {
+                *counts.entry(ngram).or_default() += 1;
+            }
+            match args.format {
+                FeatureFormat::Libsvm => {
+                    let target = match label {
+                        Label::Delete => 1,
+                        Label::Keep => 0,
+                    };
+                    write!(out, "{}", target)?;
+                    for (ngram, count) in &counts {
+                        write!(out, " {}:{}", ngram.id() + 1, count)?;
+                    }
+                    writeln!(out)?;
+                }
+                FeatureFormat::Csv => {
+                    let path = quote(&path.to_string_lossy());
+                    for (ngram, count) in &counts {
+                        let ngram = tokenizer.ngram_string(ngram).unwrap_or_default();
+                        writeln!(
+                            out,
+                            "{},{},{},{}",
+                            path,
+                            label.as_str(),
+                            quote(&ngram),
+                            count
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+    out.flush()
+}
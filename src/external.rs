@@ -0,0 +1,110 @@
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Serialize)]
+struct Request<'a> {
+    path: &'a Path,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    path: PathBuf,
+    score: f64,
+}
+
+// Scores from a user command run through the shell. Each path is written to its stdin as a
+// {"path": ...} JSON line and a {"path": ..., "score": ...} JSON line is read back from its
+// stdout per scored file, positive scores favouring delete.
+pub fn scores(command: &str, paths: &[&Path]) -> io::Result<HashMap<PathBuf, f64>> {
+    info!("Running external classifier {:?}", command);
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut lines = Vec::with_capacity(paths.len());
+    for path in paths {
+        lines.push(serde_json::to_string(&Request { path })?);
+    }
+    // Written from another thread so a command that answers as it reads can't deadlock.
+    let writer = std::thread::spawn(move || -> io::Result<()> {
+        for line in lines {
+            writeln!(stdin, "{}", line)?;
+        }
+        Ok(())
+    });
+
+    let mut scores = HashMap::new();
+    let stdout = io::BufReader::new(child.stdout.take().unwrap());
+    for line in stdout.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response: Response = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("External classifier output {:?}: {}", line, e),
+            )
+        })?;
+        scores.insert(response.path, response.score);
+    }
+
+    match writer.join().expect("External classifier writer panicked") {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => {}
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "External classifier {:?} failed: {}",
+            command, status
+        )));
+    }
+    if scores.len() < paths.len() {
+        warn!(
+            "External classifier scored {} of {} files",
+            scores.len(),
+            paths.len()
+        );
+    }
+    Ok(scores)
+}
+
+// Precomputed "path<TAB>score" lines, standardized to zero mean and unit variance so that
+// scores on any scale, such as ratings, weigh alike.
+pub fn scores_file(path: &Path) -> io::Result<HashMap<PathBuf, f64>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let mut scores = HashMap::new();
+    for line in io::BufReader::new(file).lines() {
+        let line = line?;
+        let parsed = line
+            .rsplit_once('\t')
+            .and_then(|(path, score)| Some((PathBuf::from(path), score.trim().parse().ok()?)))
+            .filter(|(_, score): &(PathBuf, f64)| score.is_finite());
+        match parsed {
+            Some((path, score)) => {
+                scores.insert(path, score);
+            }
+            None if line.trim().is_empty() => {}
+            None => warn!("Ignoring malformed score {:?}", line),
+        }
+    }
+
+    let n = scores.len() as f64;
+    let mean = scores.values().sum::<f64>() / n;
+    let var = scores.values().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+    let std = if var > 0.0 { var.sqrt() } else { 1.0 };
+    for score in scores.values_mut() {
+        *score = (*score - mean) / std;
+    }
+    Ok(scores)
+}
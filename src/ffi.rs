@@ -0,0 +1,128 @@
+// A C ABI over Model, built with:
+//
+//     cargo rustc --lib --release --features ffi --crate-type cdylib
+//
+// See include/classi_cine.h. Functions return NULL, NaN or -1 on errors, which are logged.
+use crate::{CommonArgs, Label, Model};
+use log::*;
+use std::ffi::{c_char, c_int, CStr};
+use std::path::{Path, PathBuf};
+
+pub struct CcModel {
+    model: Model,
+    // Labels trained since loading or the last save.
+    pending: Vec<(Label, PathBuf)>,
+}
+
+unsafe fn path<'a>(path: *const c_char) -> Option<&'a Path> {
+    if path.is_null() {
+        return None;
+    }
+    CStr::from_ptr(path).to_str().ok().map(Path::new)
+}
+
+/// Load the library and labels given by command line options, for example
+/// {"/videos", "--delete", "delete.txt", "--keep", "keep.txt"}.
+///
+/// # Safety
+///
+/// argv must point to argc valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cc_model_load(argc: c_int, argv: *const *const c_char) -> *mut CcModel {
+    let mut args = Vec::new();
+    for i in 0..argc.max(0) as usize {
+        let arg = *argv.add(i);
+        if arg.is_null() {
+            return std::ptr::null_mut();
+        }
+        args.push(CStr::from_ptr(arg).to_string_lossy().into_owned());
+    }
+    let common = match std::panic::catch_unwind(|| CommonArgs::try_parse_from(args)) {
+        Ok(Ok(common)) => common,
+        Ok(Err(e)) => {
+            error!("cc_model_load: {}", e);
+            return std::ptr::null_mut();
+        }
+        Err(_) => return std::ptr::null_mut(),
+    };
+    match std::panic::catch_unwind(|| Model::load(&common)) {
+        Ok(Ok(model)) => Box::into_raw(Box::new(CcModel {
+            model,
+            pending: Vec::new(),
+        })),
+        Ok(Err(e)) => {
+            error!("cc_model_load: {}", e);
+            std::ptr::null_mut()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// The score of a file, higher for likely deletes.
+///
+/// # Safety
+///
+/// model must come from cc_model_load and path be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cc_score_path(model: *const CcModel, path: *const c_char) -> f64 {
+    match (model.as_ref(), self::path(path)) {
+        (Some(model), Some(path)) => model.model.score(path),
+        _ => f64::NAN,
+    }
+}
+
+/// Train on a label, 0 for delete and 1 for keep. Labels are written to the label files by
+/// cc_model_save.
+///
+/// # Safety
+///
+/// model must come from cc_model_load and path be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cc_train_label(
+    model: *mut CcModel,
+    path: *const c_char,
+    label: c_int,
+) -> c_int {
+    let label = match label {
+        0 => Label::Delete,
+        1 => Label::Keep,
+        _ => return -1,
+    };
+    match (model.as_mut(), self::path(path)) {
+        (Some(model), Some(path)) => {
+            model.model.train(label, path);
+            model.pending.push((label, path.to_owned()));
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// Append the labels trained since loading or the last save to the label files.
+///
+/// # Safety
+///
+/// model must come from cc_model_load.
+#[no_mangle]
+pub unsafe extern "C" fn cc_model_save(model: *mut CcModel) -> c_int {
+    let Some(model) = model.as_mut() else {
+        return -1;
+    };
+    for (label, path) in std::mem::take(&mut model.pending) {
+        if let Err(e) = model.model.record(label, &path) {
+            error!("cc_model_save: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// model must come from cc_model_load, or be NULL, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cc_model_free(model: *mut CcModel) {
+    if !model.is_null() {
+        drop(Box::from_raw(model));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::pathmap;
+use crate::query::{Fields, Query};
+use crate::{seeded_hash, CommonArgs, Model};
+use clap::ValueEnum;
+use log::*;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// The unlabeled files.
+    Candidates,
+    /// The files labeled delete.
+    Delete,
+    /// The files labeled keep.
+    Keep,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct GenerateArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The M3U playlist to write.
+    #[clap(long)]
+    output: PathBuf,
+
+    /// Which files to choose from.
+    #[clap(long, default_value = "candidates")]
+    source: Source,
+
+    /// Write this many files.
+    #[clap(long, default_value = "100")]
+    top: usize,
+
+    /// Only write files matching a query, see build --help.
+    #[clap(long = "where")]
+    query: Option<Query>,
+
+    /// Shuffle the files, higher scores more likely first, instead of ordering by score. The
+    /// order is fixed by --seed.
+    #[clap(long)]
+    shuffle: bool,
+}
+
+// Write an M3U playlist of the best scoring files for any media player.
+pub fn run(args: &GenerateArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+
+    let paths: Vec<(PathBuf, u64)> = match args.source {
+        Source::Candidates => model
+            .files
+            .iter()
+            .map(|(path, meta)| (path.clone(), meta.size))
+            .collect(),
+        Source::Delete | Source::Keep => {
+            let state = match args.source {
+                Source::Delete => &model.delete,
+                _ => &model.keep,
+            };
+            state
+                .iter()
+                .map(|path| {
+                    let size = std::fs::metadata(&path)
+                        .map(|m| m.len())
+                        .unwrap_or_default();
+                    (path, size)
+                })
+                .collect()
+        }
+    };
+
+    let mut files: Vec<(f64, PathBuf)> = paths
+        .into_par_iter()
+        .filter_map(|(path, size)| {
+            let score = model.score(&path);
+            let fields = Fields {
+                path: &path,
+                size,
+                score,
+                classifier_score: score,
+            };
+            if args.query.as_ref().is_some_and(|q| !q.matches(&fields)) {
+                return None;
+            }
+            Some((score, path))
+        })
+        .collect();
+
+    if args.shuffle {
+        // Weighted sampling without replacement: sort by u^(1/w) with w = e^(score - max),
+        // compared as ln(u) / w.
+        let max = files.iter().map(|(s, _)| *s).fold(f64::MIN, f64::max);
+        let mut keyed: Vec<(f64, PathBuf)> = files
+            .into_iter()
+            .map(|(score, path)| {
+                let u =
+                    ((seeded_hash(common.seed, &path) >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+                (u.ln() / (score - max).exp(), path)
+            })
+            .collect();
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        files = keyed;
+    } else {
+        files.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    }
+
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    writeln!(out, "#EXTM3U")?;
+    for (_, path) in files.iter().take(args.top) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(out, "#EXTINF:-1,{}", name)?;
+        writeln!(out, "{}", pathmap::display(&common.path_map, path))?;
+    }
+    out.flush()?;
+
+    info!(
+        "Wrote {} of {} files to {:?}",
+        files.len().min(args.top),
+        files.len(),
+        args.output
+    );
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use crate::CommonArgs;
+use clap::ValueEnum;
+use log::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// The first line of a label file header, followed by "#key: value" comment lines.
+const MAGIC: &str = "#CLASSI-CINE:v";
+pub const VERSION: u32 = 2;
+
+// The comment header of a label file, recording when it was created and the options that
+// change how its paths tokenize, so that loading it under other options warns rather than
+// silently scoring with features the labels were not made with. Files from before headers
+// have none and are read as before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    pub created: Option<u64>,
+    pub settings: BTreeMap<String, String>,
+}
+
+impl Header {
+    pub fn new(common: &CommonArgs) -> Self {
+        Self {
+            version: VERSION,
+            created: Some(crate::now_secs()),
+            settings: settings(common),
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut ret = vec![format!("{}{}", MAGIC, self.version)];
+        if let Some(created) = self.created {
+            ret.push(format!("#created: {}", created));
+        }
+        for (key, value) in &self.settings {
+            ret.push(format!("#{}: {}", key, value));
+        }
+        ret
+    }
+
+    // The header from the leading comment lines of a label file, if it has one.
+    pub fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut lines = lines.into_iter().take_while(|line| line.starts_with('#'));
+        let version = lines.next()?.strip_prefix(MAGIC)?.parse().ok()?;
+        let mut header = Self {
+            version,
+            created: None,
+            settings: BTreeMap::new(),
+        };
+        for line in lines {
+            let Some((key, value)) = line[1..].split_once(": ") else {
+                continue;
+            };
+            match key {
+                "created" => header.created = value.parse().ok(),
+                _ => {
+                    header.settings.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        Some(header)
+    }
+
+    // Warn about a header from a newer version or options that differ from the current ones.
+    pub fn check(&self, path: &Path, common: &CommonArgs) {
+        if self.version > VERSION {
+            warn!(
+                "{:?} has a version {} header, newer than this version {}",
+                path, self.version, VERSION
+            );
+        }
+        let current = settings(common);
+        for (key, value) in &self.settings {
+            match current.get(key) {
+                Some(now) if now != value => warn!(
+                    "{:?} was labeled with --{} {} but is loaded with {}, scores may differ",
+                    path, key, value, now
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+// The options that change the tokens and ngrams of a path.
+fn settings(common: &CommonArgs) -> BTreeMap<String, String> {
+    let tokenize = common.tokenize.to_possible_value().unwrap();
+    [
+        ("tokenize", tokenize.get_name().to_string()),
+        ("windows", common.windows.to_string()),
+        ("skipgrams", common.skipgrams.to_string()),
+        ("split-case", common.split_case.to_string()),
+        ("structured-tokens", common.structured_tokens.to_string()),
+        (
+            "char-fallback",
+            common
+                .char_fallback
+                .map_or_else(|| "off".to_string(), |f| f.to_string()),
+        ),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let header = Header {
+            version: VERSION,
+            created: Some(1700000000),
+            settings: [("windows".to_string(), "20".to_string())].into(),
+        };
+        let lines = header.lines();
+        let parsed = Header::parse(lines.iter().map(String::as_str).chain(["/a.mkv"]));
+        assert_eq!(parsed, Some(header));
+    }
+
+    #[test]
+    fn legacy() {
+        assert_eq!(Header::parse(["/a.mkv", "/b.mkv"]), None);
+        assert_eq!(Header::parse(["# a comment", "/a.mkv"]), None);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::tokenizer::Ngram;
+use crate::Label;
+use clap::ValueEnum;
+use std::cmp::Ordering;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextModel {
+    /// Naive Bayes over the ngrams of all labeled files.
+    Bayes,
+    /// The labels of the most similar labeled files, for labels in several tight clusters.
+    Knn,
+    /// The sum of both scores.
+    Both,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Similarity {
+    /// Shared ngrams over all ngrams of the two files.
+    Jaccard,
+    /// Shared ngrams over the geometric mean of the ngram counts of the two files.
+    Cosine,
+}
+
+// The distinct known ngrams of a file, sorted for similarity. The unknown ngram, shared by
+// nearly every file, is left out.
+pub fn ngram_set(ngrams: &[Ngram]) -> Vec<Ngram> {
+    let mut ret: Vec<Ngram> = ngrams.iter().filter(|n| n.id() != 0).cloned().collect();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+// The similarity of two ngram sets from ngram_set.
+pub fn similarity(metric: Similarity, a: &[Ngram], b: &[Ngram]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    let shared = shared as f64;
+    match metric {
+        Similarity::Jaccard => shared / ((a.len() + b.len()) as f64 - shared),
+        Similarity::Cosine => shared / ((a.len() * b.len()) as f64).sqrt(),
+    }
+}
+
+// The k most similar labeled files of a file, most similar first.
+#[derive(Debug, Default, Clone)]
+pub struct Neighbors(Vec<(f64, Label)>);
+
+impl Neighbors {
+    pub fn insert(&mut self, k: usize, similarity: f64, label: Label) {
+        if similarity <= 0.0 {
+            return;
+        }
+        let i = self.0.partition_point(|(s, _)| *s >= similarity);
+        if i < k {
+            self.0.insert(i, (similarity, label));
+            self.0.truncate(k);
+        }
+    }
+
+    // The log ratio of the similarity weighted delete and keep votes, 0 without neighbors.
+    pub fn score(&self) -> f64 {
+        let (mut delete, mut keep) = (0.0, 0.0);
+        for (similarity, label) in &self.0 {
+            match label {
+                Label::Delete => delete += similarity,
+                Label::Keep => keep += similarity,
+            }
+        }
+        (1.0 + delete).ln() - (1.0 + keep).ln()
+    }
+}
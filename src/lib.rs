@@ -0,0 +1,2896 @@
+//! Ranks video files for deletion by a Naive Bayes classifier over the ngrams of their paths,
+//! trained on the files labeled delete and keep.
+//!
+//! The `classi-cine` binary is a thin wrapper around [`run`]. To embed the ranking instead:
+//!
+//! ```no_run
+//! use classi_cine::{Label, Model, Options};
+//!
+//! let options = Options::new(["/videos"])
+//!     .delete("delete.txt")
+//!     .keep("keep.txt")
+//!     .args(["--tokenize", "words"])
+//!     .expect("valid options");
+//! let mut model = Model::new(&options)?;
+//! for path in model.candidates() {
+//!     println!("{:.3} {}", model.score(path), path.display());
+//! }
+//! model.train(Label::Keep, "/videos/a.mkv".as_ref());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+mod tokenizer;
+use tokenizer::{FeatureRegex, Ngram, Tokenize, Tokenizer, Windows};
+
+mod walk;
+use walk::{FileMeta, Walk, WalkOptions};
+
+mod cache;
+use cache::Cache;
+
+mod vlc;
+use vlc::VLCProcessHandle;
+
+mod classifier;
+use classifier::{DirPriors, NaiveBayesClassifier};
+
+mod dupes;
+
+mod report;
+
+mod stats;
+
+mod evaluate;
+
+mod simulate;
+
+mod diff;
+
+mod score;
+
+mod session;
+
+mod pairwise;
+
+mod viz;
+
+mod color;
+
+mod config;
+
+mod list;
+
+mod export;
+
+mod external;
+
+mod media;
+
+mod thumbs;
+
+mod phash;
+
+mod similar;
+
+mod knn;
+
+mod query;
+
+mod generate;
+
+mod numeric;
+
+mod serve;
+
+mod pathmap;
+use pathmap::PathMap;
+
+mod progress;
+
+mod profile;
+use profile::time_it;
+
+mod header;
+use header::Header;
+
+mod migrate;
+
+mod sync;
+
+mod events;
+use events::Notifier;
+
+#[cfg(feature = "ffi")]
+mod ffi;
+use color::{ColorChoice, Painter};
+use knn::{Neighbors, TextModel};
+use numeric::NumericModel;
+use query::Query;
+use session::{ResumeState, Session};
+use viz::{ScoreVisualizer, Viz};
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use humansize::{format_size, BINARY};
+use indicatif::ParallelProgressIterator;
+use log::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug)]
+#[allow(dead_code)]
+enum Error {
+    Reqwest(reqwest::Error),
+    SerdeJson(serde_json::Error),
+    Timeout,
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Reqwest(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::SerdeJson(e)
+    }
+}
+
+// Set by the Ctrl-C handler, the interactive session ends after the current file.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+fn round(v: f64) -> f64 {
+    (v * 1_000.0).round() / 1_000.0
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// The UTC date of a unix timestamp as YYYY-MM-DD, by Howard Hinnant's civil_from_days.
+fn date(secs: u64) -> String {
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// A length of time like "90d", in seconds. Suffixes are s, m, h, d, w and y, days by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Period(f64);
+
+impl std::str::FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+        let unit = match unit {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "" | "d" => 86400.0,
+            "w" => 7.0 * 86400.0,
+            "y" => 365.0 * 86400.0,
+            _ => {
+                return Err(format!(
+                    "unknown unit in {:?}, expected s, m, h, d, w or y",
+                    s
+                ))
+            }
+        };
+        let value: f64 = value
+            .trim()
+            .parse()
+            .map_err(|e| format!("{:?}: {}", s, e))?;
+        Ok(Period(value * unit))
+    }
+}
+
+// A stable pseudo random value per path, used for seeded orderings.
+fn seeded_hash(seed: u64, path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    path.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[command(flatten)]
+    vlc: vlc::VlcArgs,
+
+    /// The log base for the file size which is mixed into the classifier score to preference
+    /// larger files over smaller files. Recommended values are close to 1.0, for example 1.1,
+    /// 1.01, 1.001, and so on.
+    #[clap(long)]
+    file_size_log_base: Option<f64>,
+
+    /// Don't keep ngrams in memory between iterations. They are recomputed for every file
+    /// after each classification instead, which is much slower on large libraries.
+    #[clap(long)]
+    low_memory: bool,
+
+    /// The number of most recent suggestions the rolling precision is computed over.
+    #[clap(long, default_value = "20")]
+    precision_k: usize,
+
+    /// Append the label, score and rolling precision of each classification to this CSV file.
+    #[clap(long)]
+    metrics: Option<PathBuf>,
+
+    /// Append a summary of each session as a JSON line to this file.
+    #[clap(long)]
+    history: Option<PathBuf>,
+
+    /// POST the score of each suggested file, each classification and the session summary as
+    /// JSON to this URL, with an "event" field of "score", "label" or "session_end", for example
+    /// to trigger jobs downstream.
+    #[clap(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Publish the --webhook events to an MQTT broker, for example tcp://broker:1883.
+    #[cfg(feature = "mqtt")]
+    #[clap(long, value_name = "URL")]
+    mqtt: Option<String>,
+
+    /// The MQTT topic the events are published to.
+    #[cfg(feature = "mqtt")]
+    #[clap(long, default_value = "classi-cine/events")]
+    mqtt_topic: String,
+
+    /// The score above which unlabeled files are counted in the session summary.
+    #[clap(long, default_value = "0.0")]
+    summary_threshold: f64,
+
+    /// The file an interrupted session is saved to.
+    #[clap(long, default_value = "session.json")]
+    session_file: PathBuf,
+
+    /// Continue the session saved by an earlier Ctrl-C, starting with the file that was
+    /// playing.
+    #[clap(long)]
+    resume: bool,
+
+    /// End the session after this many classifications.
+    #[clap(long)]
+    max_labels: Option<usize>,
+
+    /// End the session after the file playing when this many minutes have passed.
+    #[clap(long)]
+    max_minutes: Option<f64>,
+
+    /// End the session once the Kendall tau between the top ranked files of successive
+    /// iterations stays at or above this value, i.e. new labels no longer change the ranking.
+    #[clap(long)]
+    stable_tau: Option<f64>,
+
+    /// The number of top ranked files compared by --stable-tau.
+    #[clap(long, default_value = "50")]
+    stable_top: usize,
+
+    /// The number of successive iterations the ranking must stay stable for.
+    #[clap(long, default_value = "5")]
+    stable_patience: usize,
+
+    /// After each label, list this many unclassified files whose scores rose and fell the most.
+    #[clap(long, default_value = "5")]
+    score_impact: usize,
+
+    /// Label files one at a time, or compare pairs of files and rank them by preference.
+    #[clap(long, default_value = "label")]
+    mode: Mode,
+
+    /// The file pairwise comparisons are read from and appended to.
+    #[clap(long, default_value = "comparisons.txt")]
+    comparisons: PathBuf,
+
+    /// The weight of the Bradley-Terry strength fitted to the pairwise comparisons, which is
+    /// added to the score of each file.
+    #[clap(long, default_value = "1.0")]
+    pairwise_weight: f64,
+
+    /// A shell command scoring the candidate files, which reads {"path": ...} JSON lines on
+    /// stdin and writes {"path": ..., "score": ...} JSON lines to stdout. Positive scores favour
+    /// delete.
+    #[clap(long)]
+    external_classifier: Option<String>,
+
+    /// The weight of the --external-classifier score added to the score of each file.
+    #[clap(long, default_value = "1.0")]
+    external_weight: f64,
+
+    /// A file of "path<TAB>score" lines, such as ratings from a media server. The scores are
+    /// standardized and, times --scores-weight, added to the external score of each file.
+    #[clap(long)]
+    scores_file: Option<PathBuf>,
+
+    /// The weight of the standardized --scores-file scores.
+    #[clap(long, default_value = "1.0")]
+    scores_weight: f64,
+
+    /// Add this weight times the pHash similarity of each file to the most similar file
+    /// labeled delete, to catch re-encodes. Frames are decoded with ffmpeg and the hashes kept
+    /// in the --cache file.
+    #[clap(long)]
+    phash_weight: Option<f64>,
+
+    /// The pHash similarity, the fraction of matching bits, below which files are unrelated.
+    #[clap(long, default_value = "0.9")]
+    phash_threshold: f64,
+
+    /// The text model scoring files by their ngrams.
+    #[clap(long, default_value = "bayes")]
+    text_model: TextModel,
+
+    /// The number of nearest labeled files that vote in the kNN text model.
+    #[clap(long, default_value = "5")]
+    knn_k: usize,
+
+    /// How similar the ngrams of two files are in the kNN text model.
+    #[clap(long, default_value = "jaccard")]
+    knn_similarity: knn::Similarity,
+
+    /// The weight of the kNN score, the log ratio of the similarity weighted delete and keep
+    /// votes.
+    #[clap(long, default_value = "1.0")]
+    knn_weight: f64,
+
+    /// Only play files matching a query like 'size > 1GB && score > 0.8 && path ~ "*/2023/*"',
+    /// evaluated at the current scores before each file. Fields: size, score, classifier, path,
+    /// name, ext. Operators: == != < <= > >= ~ !~ (glob), combined with && || ! and parentheses.
+    #[clap(long = "where")]
+    query: Option<Query>,
+
+    /// How the score distribution is charted before each file.
+    #[clap(long, default_value = "histogram")]
+    viz: Viz,
+
+    /// Print one JSON line per classification instead of charts and file details.
+    #[clap(long)]
+    porcelain: bool,
+
+    /// Keep files once playback gets past this fraction of their length, e.g. 0.8, without
+    /// waiting for a pause.
+    #[clap(long)]
+    keep_past: Option<f64>,
+
+    /// Delete files when VLC is closed before playback reaches this fraction of their length,
+    /// e.g. 0.2.
+    #[clap(long)]
+    delete_before: Option<f64>,
+
+    /// Start playback at this offset into each file, as a percentage ("30%") or seconds ("90s").
+    #[clap(long)]
+    preview_start: Option<vlc::Offset>,
+
+    /// Skip to the next file without labeling after playing this much of the current one, as a
+    /// percentage ("10%") or seconds ("20s").
+    #[clap(long)]
+    preview_length: Option<vlc::Offset>,
+
+    /// Stop playback and skip a file when it has not been labeled after this many seconds.
+    #[clap(long)]
+    classify_timeout: Option<u64>,
+
+    /// Play files skipped by --classify-timeout again after the other files.
+    #[clap(long)]
+    requeue: bool,
+
+    /// What to do when playback reaches the end of a file without a label.
+    #[clap(long, default_value = "skip")]
+    on_end: EndAction,
+
+    /// Overlay the controls, rank and score of each file on the video.
+    #[clap(long)]
+    osd: bool,
+
+    /// Queue this many of the best ranked files into the VLC playlist at once, skipping to the
+    /// next playlist item labels the current file keep. The ranking is refreshed between batches.
+    #[clap(long, default_value = "1")]
+    batch: usize,
+
+    /// Reorder the rest of the batch by the updated scores after each label, instead of
+    /// playing it in the order it was queued in. The files stay in the batch.
+    #[clap(long)]
+    rerank_batch: bool,
+
+    /// Before each batch, list this many of the best ranked files to label from their names
+    /// alone, drop from the session or play first.
+    #[clap(long, value_name = "K", default_value = "0")]
+    queue_preview: usize,
+
+    /// Label each file from its name and score explanation at a y/n/s prompt instead of playing
+    /// it, for obvious cases or over SSH.
+    #[clap(long)]
+    no_player: bool,
+
+    /// Hold the labels of each batch until it ends, then list them and write them only once
+    /// confirmed, with a veto for each. Vetoed files are ranked again.
+    #[clap(long)]
+    confirm_batch: bool,
+
+    /// Queue the best ranked of the remaining files after the one playing, so VLC has it open
+    /// by the time the current file is labeled. It plays next unless the label changes the
+    /// ranking, in which case the new best file is loaded as usual.
+    #[clap(long)]
+    prebuffer: bool,
+
+    /// Print the files the session would play first with their score breakdown, then exit.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// How many files --dry-run prints.
+    #[clap(long, default_value = "20")]
+    dry_run_top: usize,
+
+    /// Color scores, classifier names and ngram explanations.
+    #[clap(long, default_value = "auto")]
+    color: ColorChoice,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    /// Human readable lines, as on the terminal.
+    Text,
+    /// One JSON object per line with timestamp, level, target and message fields.
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Play one file and pause (keep) or stop (delete) it.
+    Label,
+    /// Play two files and pause on the preferred one, or stop to skip the pair.
+    Pairwise,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum EndAction {
+    /// Play the file again and keep waiting for a label.
+    Replay,
+    /// Move on without labeling the file.
+    Skip,
+    /// Label the file keep, as if it was paused.
+    Keep,
+    /// Label the file delete, as if it was stopped.
+    Delete,
+}
+
+impl Args {
+    fn common(&self) -> &CommonArgs {
+        match &self.command {
+            Some(Command::Dupes(args)) => &args.common,
+            Some(Command::Report(args)) => args.common(),
+            Some(Command::Stats(args)) => &args.common,
+            Some(Command::Evaluate(args)) => &args.common,
+            Some(Command::Simulate(args)) => &args.common,
+            Some(Command::Diff(args)) => &args.common,
+            Some(Command::Score(args)) => &args.common,
+            Some(Command::List(args)) => &args.common,
+            Some(Command::Export(args)) => &args.common,
+            Some(Command::ExportFeatures(args)) => &args.common,
+            Some(Command::Thumbs(args)) => &args.common,
+            Some(Command::Similar(args)) => &args.common,
+            Some(Command::Generate(args)) => &args.common,
+            Some(Command::ServeApi(args)) => &args.common,
+            Some(Command::Migrate(args)) => &args.common,
+            Some(Command::Sync(args)) => &args.common,
+            None => &self.common,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Find likely duplicate videos by file size and partial content hash.
+    Dupes(dupes::DupesArgs),
+    /// Inspect the trained model.
+    Report(report::ReportArgs),
+    /// Print label counts and model size.
+    Stats(stats::StatsArgs),
+    /// Cross-validate the classifier on the labeled files.
+    Evaluate(evaluate::EvaluateArgs),
+    /// Replay the existing labels from scratch to measure how quickly deletes are found.
+    Simulate(simulate::SimulateArgs),
+    /// Compare two label sets and the models trained on them.
+    Diff(diff::DiffArgs),
+    /// Print the scores of the unlabeled files.
+    Score(score::ScoreArgs),
+    /// List the labeled files.
+    List(list::ListArgs),
+    /// Write the labeled files with their scores as CSV or TSV.
+    Export(export::ExportArgs),
+    /// Write the ngram features of the labeled files for external learners.
+    ExportFeatures(export::ExportFeaturesArgs),
+    /// Make ffmpeg contact sheets of the unlabeled files for triage without playback.
+    Thumbs(thumbs::ThumbsArgs),
+    /// Rank the files by ngram similarity to a file, to find the rest of a series.
+    Similar(similar::SimilarArgs),
+    /// Write an M3U playlist of the best scoring files for any media player.
+    Generate(generate::GenerateArgs),
+    /// Answer score, rank and label requests from other tools over HTTP.
+    ServeApi(serve::ServeArgs),
+    /// Upgrade the label files to the current format, with a header, normalized paths and no
+    /// duplicates.
+    Migrate(migrate::MigrateArgs),
+    /// Merge the label files of two machines, resolving conflicts by label time.
+    Sync(sync::SyncArgs),
+}
+
+/// Arguments shared by the interactive mode and subcommands.
+#[derive(clap::Args, Debug, Clone)]
+pub(crate) struct CommonArgs {
+    /// The library directories, required unless given by --files-from, scoring --stdin or
+    /// migrating or syncing the label files.
+    paths: Vec<PathBuf>,
+
+    /// Read candidate file paths, one per line, from a file or "-" for stdin.
+    #[clap(long)]
+    files_from: Option<PathBuf>,
+
+    /// The tokenizer to use.
+    #[clap(long, default_value = "chars")]
+    tokenize: Tokenize,
+
+    /// The ngram (window of tokens) sizes: N for 1 to N tokens, a range like 2..5 to leave out
+    /// single tokens, or a list like 1,3,5.
+    #[clap(long, alias = "ngram-sizes", default_value = "20")]
+    windows: Windows,
+
+    /// Also create skip-grams: windows one token longer than each ngram size from 2 up, with
+    /// one interior token left out.
+    #[clap(long)]
+    skipgrams: bool,
+
+    /// Split words at camelCase and letter-digit boundaries, "MovieName2019Remastered" becomes
+    /// "movie name 2019 remastered".
+    #[clap(long)]
+    split_case: bool,
+
+    /// Replace years like (2019), season and episode markers like S01E02 and episode numbers like
+    /// EP12 with structured tokens such as <YEAR:2019>, <SEASON> and <EPISODE>.
+    #[clap(long)]
+    structured_tokens: bool,
+
+    /// Add the captures of a named regex over the lowercased path as extra tokens, for example
+    /// 'res:(\d{3,4}p)' adds <res:1080p>. Can be repeated.
+    #[clap(long, value_name = "NAME:REGEX")]
+    feature_regex: Vec<FeatureRegex>,
+
+    /// With --tokenize words, when at least this fraction of the tokens of a path, directories
+    /// included, are words missing from the vocabulary, add the character 3 to 5 grams of those
+    /// words as extra tokens, so that paths named in a new way still share features with the
+    /// labeled files.
+    #[clap(long, value_name = "FRACTION")]
+    char_fallback: Option<f64>,
+
+    /// Words to leave out of ngrams, for example codec and source boilerplate.
+    #[arg(long, value_delimiter = ',')]
+    stop_tokens: Vec<String>,
+
+    /// A text file of stop tokens, one per line.
+    #[clap(long)]
+    stop_tokens_file: Option<PathBuf>,
+
+    /// Weight each ngram's contribution to the score by its inverse document frequency, damping
+    /// ngrams common to most files.
+    #[clap(long)]
+    idf: bool,
+
+    /// The minimum number of files a token or ngram must appear in to be used as a feature.
+    #[clap(long, default_value = "2")]
+    min_freq: usize,
+
+    /// Limit the vocabulary to the N tokens appearing in the most files, including the
+    /// --char-fallback character ngrams.
+    #[clap(long)]
+    max_vocab: Option<usize>,
+
+    /// Build the vocabulary from a random sample of this many files when the library is
+    /// larger, bounding the startup time on huge libraries. Frequencies in the sample are
+    /// scaled to the whole library and every file is tokenized with the sampled vocabulary. The
+    /// sample depends on --seed.
+    #[clap(long, value_name = "N")]
+    tokenizer_sample: Option<usize>,
+
+    /// Every 1000 labels, drop the ngrams counted fewer than this many times in the labeled
+    /// files from the model, keeping an approximate count of them in a count-min sketch.
+    #[clap(long, value_name = "COUNT")]
+    prune_below: Option<f64>,
+
+    /// Keep exact delete and keep counts for at most N ngrams, pruning the least counted into a
+    /// count-min sketch, to bound the memory of the model for very large label sets.
+    #[clap(long, value_name = "N")]
+    max_model_ngrams: Option<usize>,
+
+    /// Count ngrams in 2^BITS hashed buckets, for example 20, instead of exactly. Memory is
+    /// fixed and lookups index a dense array, at the cost of colliding ngrams sharing counts.
+    #[clap(
+        long,
+        value_name = "BITS",
+        value_parser = clap::value_parser!(u32).range(1..=30),
+        conflicts_with_all = ["prune_below", "max_model_ngrams"]
+    )]
+    hash_bits: Option<u32>,
+
+    /// Condition the Naive Bayes prior on the directory of each file: the root it was found
+    /// under and up to N directories below it, so 0 gives a prior per root and 1 a prior per
+    /// top-level directory. Learned from the labeled files in each directory.
+    #[clap(long)]
+    dir_prior_depth: Option<usize>,
+
+    /// The pseudo counts at the overall delete rate added to each directory prior, larger
+    /// values need more labels in a directory to move its prior.
+    #[clap(long, default_value = "10")]
+    dir_prior_strength: f64,
+
+    /// Learn how the file size, age and size of the directory relate to the labels,
+    /// as binned likelihoods added to the Naive Bayes score, instead of hand tuning
+    /// --file-size-log-base.
+    #[clap(long)]
+    learn_numeric: bool,
+
+    /// The number of quantile bins per numeric feature with --learn-numeric.
+    #[clap(long, default_value = "8")]
+    numeric_bins: usize,
+
+    /// How the size of the directory of a file is measured with --learn-numeric. A directory of
+    /// three huge files and one of hundreds of clips differ in bytes, not only in file counts.
+    #[clap(long, default_value = "files")]
+    numeric_dir_size: numeric::DirSize,
+
+    /// The timestamp the file age is measured from with --learn-numeric. Downloaded files often
+    /// keep the modification time of the upload.
+    #[clap(long, default_value = "created")]
+    age_from: numeric::AgeFrom,
+
+    /// Halve the weight of a label in the Naive Bayes counts every period since it was made,
+    /// for example "90d", so that old labels count less as taste drifts. Labels made before
+    /// timestamps were recorded, in the "<label file>.times" sidecar, count fully.
+    #[clap(long, value_name = "PERIOD")]
+    label_half_life: Option<Period>,
+
+    /// The text file containing the files to delete.
+    #[clap(long, default_value = "delete.txt")]
+    delete: PathBuf,
+
+    /// The text file containing the files to keep.
+    #[clap(long, default_value = "keep.txt")]
+    keep: PathBuf,
+
+    /// Keep the label files and their timestamps sorted by path, rewriting them on each label,
+    /// so that they diff cleanly under version control. By default labels are appended, which
+    /// is faster for large label files.
+    #[clap(long, alias = "sorted-playlist")]
+    sorted_labels: bool,
+
+    #[clap(long, default_value = "info")]
+    log_level: String,
+
+    /// Append the log to this file instead of the terminal, keeping a record of the
+    /// classifications, scores, timings and VLC errors of long sessions apart from the
+    /// interactive output.
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+
+    #[clap(long, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Write the time taken by each phase, such as walking, tokenizing, training and scoring,
+    /// and the peak memory use to this JSON file on exit.
+    #[clap(long)]
+    profile_out: Option<PathBuf>,
+
+    /// A TOML file of option defaults, read from ~/.config/classi-cine/config.toml when it
+    /// exists. Options given on the command line take precedence.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Seed for ordering files with equal scores. Identical inputs and seeds give identical
+    /// rankings.
+    #[clap(long, default_value = "0")]
+    seed: u64,
+
+    /// Cache file for file metadata and tokenization results, reused between runs for files
+    /// whose size and modification time are unchanged. The vocabulary and ngrams of the files
+    /// are reused while no file and no tokenizer setting changed.
+    #[clap(long)]
+    cache: Option<PathBuf>,
+
+    /// How many ffprobe and ffmpeg processes run at once for durations, pHashes and contact
+    /// sheets, by default one per CPU. Durations and pHashes are kept in the --cache file.
+    #[clap(long, value_name = "N")]
+    probe_jobs: Option<usize>,
+
+    /// Follow symlinked directories and files while walking. Each directory is walked at most
+    /// once, so symlink cycles are safe.
+    #[clap(long)]
+    follow_symlinks: bool,
+
+    /// Abort on the first error walking the library, such as an unreadable directory, instead
+    /// of logging the errors and a summary of them per directory and carrying on without the
+    /// files that could not be read.
+    #[clap(long)]
+    strict_walk: bool,
+
+    /// Keep files reachable through several paths (hardlinks, overlapping roots) as separate
+    /// entries instead of collapsing them into one.
+    #[clap(long)]
+    no_dedup: bool,
+
+    /// Resolve symlinks in the paths of the library files and the labeled files, so that a
+    /// file reached through a symlinked root and its real path is one file with one label.
+    /// Paths that cannot be resolved are used as given.
+    #[clap(long)]
+    canonicalize: bool,
+
+    /// Rewrite a path prefix in playlists and in the paths handed to the player, may be
+    /// repeated, for example --path-map '/mnt/nas=\\NAS\media'. The first matching map wins.
+    #[clap(long, value_name = "FROM=TO")]
+    path_map: Vec<PathMap>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "avi,flv,mov,f4v,flv,m2ts,m4v,mkv,mpg,webm,wmv,mp4"
+    )]
+    video_exts: Vec<String>,
+
+    #[command(flatten)]
+    media: media::MediaArgs,
+}
+
+impl CommonArgs {
+    // Parse the library paths and options shared by all commands, as given on the command
+    // line.
+    fn try_parse_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        #[derive(Parser)]
+        struct Common {
+            #[command(flatten)]
+            common: CommonArgs,
+        }
+        let args = std::iter::once("classi-cine".into()).chain(args.into_iter().map(Into::into));
+        Ok(Common::try_parse_from(args)?.common)
+    }
+}
+
+/// The library and label files a [`Model`] is built from. Everything else defaults as on the
+/// command line, see [`Options::args`].
+#[derive(Debug, Clone)]
+pub struct Options {
+    paths: Vec<PathBuf>,
+    delete: PathBuf,
+    keep: PathBuf,
+    common: CommonArgs,
+}
+
+impl Options {
+    /// The library directories, with the label files delete.txt and keep.txt.
+    pub fn new<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> Self {
+        let common =
+            CommonArgs::try_parse_from(std::iter::empty::<String>()).expect("default options");
+        let mut options = Self {
+            paths: paths.into_iter().map(Into::into).collect(),
+            delete: common.delete.clone(),
+            keep: common.keep.clone(),
+            common,
+        };
+        options.apply();
+        options
+    }
+
+    /// The file of the paths labeled delete.
+    pub fn delete(mut self, path: impl Into<PathBuf>) -> Self {
+        self.delete = path.into();
+        self.apply();
+        self
+    }
+
+    /// The file of the paths labeled keep.
+    pub fn keep(mut self, path: impl Into<PathBuf>) -> Self {
+        self.keep = path.into();
+        self.apply();
+        self
+    }
+
+    /// Other options as given on the command line, for example `["--tokenize", "words"]`.
+    /// Replaces the options of an earlier call. The library and label files stay those given
+    /// to the builder.
+    pub fn args<I, T>(mut self, args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        self.common = CommonArgs::try_parse_from(args)?;
+        self.apply();
+        Ok(self)
+    }
+
+    fn apply(&mut self) {
+        self.common.paths = self.paths.clone();
+        self.common.delete = self.delete.clone();
+        self.common.keep = self.keep.clone();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Label {
+    Delete,
+    Keep,
+}
+
+impl Label {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Label::Delete => "delete",
+            Label::Keep => "keep",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    path: PathBuf,
+    contents: Vec<String>,
+    // When each entry was labeled, in seconds since the unix epoch, from the "<path>.times"
+    // sidecar of "timestamp<TAB>entry" lines. Entries labeled before timestamps were recorded
+    // have none.
+    times: HashMap<String, u64>,
+    // The comment header the file was read with, see header.rs.
+    header: Option<Header>,
+    // The header to write before the first entry of a new file.
+    new_header: Option<Header>,
+    // Keep the file sorted by path on update, see --sorted-labels.
+    sorted: bool,
+}
+
+// A label file entry written on another platform or by hand, as the directory walk names it.
+fn normalize_entry(entry: String) -> String {
+    if cfg!(windows) {
+        walk::normalize(Path::new(&entry))
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        entry
+    }
+}
+
+// Rewrite a file with its leading comment lines first and the rest sorted by key, through a
+// temporary file so that an interruption leaves the old file in place.
+fn sort_lines(path: &Path, key: impl Fn(&str) -> &str) -> io::Result<()> {
+    let lines: Vec<String> = io::BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<io::Result<_>>()?;
+    let comments = lines.iter().take_while(|l| l.starts_with('#')).count();
+    let (comments, entries) = lines.split_at(comments);
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| key(a).cmp(key(b)).then_with(|| a.cmp(b)));
+
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let mut out = io::BufWriter::new(File::create(&tmp)?);
+    for line in comments.iter().chain(&entries) {
+        writeln!(out, "{}", line)?;
+    }
+    out.into_inner()?.sync_all()?;
+    std::fs::rename(tmp, path)
+}
+
+impl State {
+    fn new(path: &Path) -> State {
+        State {
+            path: path.to_owned(),
+            contents: Vec::new(),
+            times: HashMap::new(),
+            header: None,
+            new_header: None,
+            sorted: false,
+        }
+    }
+
+    fn times_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".times");
+        PathBuf::from(path)
+    }
+
+    fn load(&mut self) -> io::Result<()> {
+        match File::open(&self.path) {
+            Ok(file) => {
+                let reader = io::BufReader::new(file);
+                let lines: Vec<String> = reader.lines().map_while(Result::ok).collect();
+                self.header = Header::parse(lines.iter().map(String::as_str));
+                for line in lines {
+                    if !line.starts_with('#') {
+                        self.contents.push(normalize_entry(line));
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        match File::open(self.times_path()) {
+            Ok(file) => {
+                let reader = io::BufReader::new(file);
+                for line in reader.lines().map_while(Result::ok) {
+                    if let Some((time, entry)) = line.split_once('\t') {
+                        if let Ok(time) = time.parse() {
+                            self.times.insert(normalize_entry(entry.to_string()), time);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn from(path: &Path) -> io::Result<State> {
+        let mut state = State::new(path);
+        state.load()?;
+        Ok(state)
+    }
+
+    // Load a label file with the entries resolved as the options ask, see --canonicalize.
+    fn load_args(path: &Path, common: &CommonArgs) -> io::Result<State> {
+        let mut state = State::from(path)?;
+        state.sorted = common.sorted_labels;
+        match &state.header {
+            Some(header) => header.check(path, common),
+            None if state.contents.is_empty() => state.new_header = Some(Header::new(common)),
+            None => {}
+        }
+        if common.canonicalize {
+            state.canonicalize();
+        }
+        Ok(state)
+    }
+
+    // Resolve symlinks in the entries in memory, the label file is unchanged. Entries that
+    // cannot be resolved, such as deleted files, are kept as written.
+    fn canonicalize(&mut self) {
+        let canonical = |entry: &String| match std::fs::canonicalize(entry) {
+            Ok(path) => walk::normalize(&path).to_string_lossy().into_owned(),
+            Err(_) => entry.clone(),
+        };
+        self.contents = self.contents.par_iter().map(canonical).collect();
+        self.times = self
+            .times
+            .iter()
+            .map(|(entry, time)| (canonical(entry), *time))
+            .collect();
+    }
+
+    fn update(&mut self, line: &str) -> io::Result<()> {
+        self.contents.push(line.to_owned());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if let Some(header) = self.new_header.take() {
+            for line in header.lines() {
+                writeln!(file, "{}", line)?;
+            }
+            self.header = Some(header);
+        }
+        writeln!(file, "{}", line)?;
+
+        let now = now_secs();
+        self.times.insert(line.to_owned(), now);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.times_path())?;
+        writeln!(file, "{}\t{}", now, line)?;
+
+        if self.sorted {
+            sort_lines(&self.path, |line| line)?;
+            sort_lines(&self.times_path(), |line| {
+                line.split_once('\t').map_or(line, |(_, entry)| entry)
+            })?;
+        }
+        Ok(())
+    }
+
+    // When a path was labeled, if recorded.
+    fn timestamp(&self, path: &Path) -> Option<u64> {
+        self.times.get(path.to_string_lossy().as_ref()).cloned()
+    }
+
+    // The training weight of a labeled path, halved every --label-half-life since it was
+    // labeled. Paths without a timestamp count fully.
+    fn weight(&self, path: &Path, half_life: Option<Period>, now: u64) -> f64 {
+        match (half_life, self.timestamp(path)) {
+            (Some(Period(half_life)), Some(time)) if half_life > 0.0 => {
+                0.5f64.powf(now.saturating_sub(time) as f64 / half_life)
+            }
+            _ => 1.0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.contents.iter().map(PathBuf::from)
+    }
+}
+
+#[derive(Debug, Default)]
+struct FileState {
+    path: PathBuf,
+    // Classifier state, ngrams are recomputed on demand in low memory mode.
+    ngrams: Option<Vec<Ngram>>,
+    ngram_weight: f64,
+    ngram_score: f64,
+    // Log odds from the directory and numeric features of the file rather than its ngrams.
+    meta_score: f64,
+    classifier_score: f64,
+    // File size state.
+    file_size: u64,
+    file_size_score: f64,
+    // Preference strength from pairwise comparisons.
+    pairwise_score: f64,
+    external_score: f64,
+    phash_score: f64,
+    // The nearest labeled files for the kNN text model.
+    knn: Neighbors,
+    knn_score: f64,
+
+    score: f64,
+    // Orders files with equal scores, derived from the path and --seed.
+    tie_break: u64,
+}
+
+impl FileState {
+    fn new(
+        path: PathBuf,
+        ngrams: Vec<Ngram>,
+        file_size: u64,
+        file_size_log_base: Option<f64>,
+        seed: u64,
+    ) -> Self {
+        let tie_break = seeded_hash(seed, &path);
+
+        let file_size_score = if let Some(base) = file_size_log_base {
+            ((file_size + 1) as f64).log(base)
+        } else {
+            0.0
+        };
+        Self {
+            path,
+            ngrams: Some(ngrams),
+            ngram_weight: 0.0,
+            file_size,
+            file_size_score,
+            pairwise_score: 0.0,
+            external_score: 0.0,
+            phash_score: 0.0,
+            knn: Neighbors::default(),
+            knn_score: 0.0,
+            ngram_score: 0.0,
+            meta_score: 0.0,
+            classifier_score: 0.0,
+            score: 0.0,
+            tie_break,
+        }
+    }
+
+    fn ngrams(&self, tokenizer: &Tokenizer) -> Cow<'_, [Ngram]> {
+        match &self.ngrams {
+            Some(ngrams) => Cow::Borrowed(ngrams),
+            None => Cow::Owned(tokenizer.ngrams_cached(&self.path)),
+        }
+    }
+
+    fn init(&mut self, classifier: &NaiveBayesClassifier, tokenizer: &Tokenizer) {
+        let ngrams = self.ngrams(tokenizer);
+        let mut weight = 0.0;
+        let mut score = 0.0;
+        for ngram in ngrams.iter() {
+            let w = classifier.weight(ngram);
+            weight += w;
+            score += w * classifier.ngram_log_odds(ngram);
+        }
+        self.ngram_weight = weight;
+        self.ngram_score = score;
+        self.meta_score = classifier.meta_log_odds(&self.path);
+    }
+
+    fn fields(&self) -> query::Fields<'_> {
+        query::Fields {
+            path: &self.path,
+            size: self.file_size,
+            score: self.score,
+            classifier_score: self.classifier_score,
+        }
+    }
+
+    fn update(&mut self, norm_log_odds: f64) {
+        self.classifier_score = self.classifier_score_at(norm_log_odds);
+        self.score = self.score_at(norm_log_odds);
+    }
+
+    // The scores update would set, without updating the file.
+    fn classifier_score_at(&self, norm_log_odds: f64) -> f64 {
+        self.meta_score + self.ngram_score + self.ngram_weight * norm_log_odds
+    }
+
+    fn score_at(&self, norm_log_odds: f64) -> f64 {
+        self.file_size_score
+            + self.pairwise_score
+            + self.external_score
+            + self.phash_score
+            + self.knn_score
+            + self.classifier_score_at(norm_log_odds)
+    }
+
+    fn debug(&self, tokenizer: &Tokenizer, classifier: &NaiveBayesClassifier, painter: &Painter) {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        struct Current<'a> {
+            path: &'a Path,
+            size: String,
+            classifier_score: f64,
+            file_size_score: f64,
+            pairwise_score: f64,
+            external_score: f64,
+            phash_score: f64,
+            knn_score: f64,
+            ngrams: Vec<(f64, String)>,
+        }
+        let debug = Current {
+            path: &self.path,
+            size: format_size(self.file_size, BINARY),
+            classifier_score: round(self.classifier_score),
+            file_size_score: round(self.file_size_score),
+            pairwise_score: round(self.pairwise_score),
+            external_score: round(self.external_score),
+            phash_score: round(self.phash_score),
+            knn_score: round(self.knn_score),
+            ngrams: classifier.debug_delete(tokenizer, &self.ngrams(tokenizer)),
+        };
+        if !painter.enabled() {
+            println!("{:?}", debug);
+            return;
+        }
+
+        let ngrams: Vec<String> = debug
+            .ngrams
+            .iter()
+            .map(|(score, ngram)| format!("({}, {:?})", painter.score(*score), ngram))
+            .collect();
+        println!(
+            "Current {{ path: {}, size: {:?}, {}: {}, {}: {}, {}: {}, {}: {}, {}: {}, {}: {}, ngrams: [{}] }}",
+            painter.path(format!("{:?}", debug.path)),
+            debug.size,
+            painter.name("classifier_score"),
+            painter.score(debug.classifier_score),
+            painter.name("file_size_score"),
+            painter.score(debug.file_size_score),
+            painter.name("pairwise_score"),
+            painter.score(debug.pairwise_score),
+            painter.name("external_score"),
+            painter.score(debug.external_score),
+            painter.name("phash_score"),
+            painter.score(debug.phash_score),
+            painter.name("knn_score"),
+            painter.score(debug.knn_score),
+            ngrams.join(", ")
+        );
+    }
+}
+
+// The Kendall tau rank correlation of the files ranked in both prev and cur, ordered best first.
+// None when fewer than two files are shared.
+fn kendall_tau(prev: &[usize], cur: &[usize]) -> Option<f64> {
+    let prev_rank: HashMap<usize, usize> = prev.iter().enumerate().map(|(r, i)| (*i, r)).collect();
+    let ranks: Vec<usize> = cur
+        .iter()
+        .filter_map(|i| prev_rank.get(i).cloned())
+        .collect();
+    let n = ranks.len();
+    if n < 2 {
+        return None;
+    }
+    let mut concordant = 0i64;
+    let mut discordant = 0i64;
+    for i in 0..n {
+        for j in i + 1..n {
+            if ranks[i] < ranks[j] {
+                concordant += 1;
+            } else {
+                discordant += 1;
+            }
+        }
+    }
+    Some((concordant - discordant) as f64 / (n * (n - 1) / 2) as f64)
+}
+
+// Fit Platt scaling, P(delete) = 1 / (1 + exp(-(a * score + b))), to scores predicted before
+// their files were labeled. None until both labels have been seen.
+fn platt_scaling(examples: &[(f64, Label)]) -> Option<(f64, f64)> {
+    let positives = examples.iter().filter(|e| e.1 == Label::Delete).count();
+    let negatives = examples.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+    // Smoothed targets as in Platt's paper, to avoid overfitting separable scores.
+    let t_pos = (positives as f64 + 1.0) / (positives as f64 + 2.0);
+    let t_neg = 1.0 / (negatives as f64 + 2.0);
+
+    let (mut a, mut b) = (0.0, ((positives + 1) as f64 / (negatives + 1) as f64).ln());
+    for _ in 0..100 {
+        let (mut g_a, mut g_b) = (0.0, 0.0);
+        let (mut h_aa, mut h_ab, mut h_bb) = (1e-6, 0.0, 1e-6);
+        for (score, label) in examples {
+            let t = if *label == Label::Delete {
+                t_pos
+            } else {
+                t_neg
+            };
+            let p = 1.0 / (1.0 + (-(a * score + b)).exp());
+            let w = p * (1.0 - p);
+            g_a += (p - t) * score;
+            g_b += p - t;
+            h_aa += w * score * score;
+            h_ab += w * score;
+            h_bb += w;
+        }
+        let det = h_aa * h_bb - h_ab * h_ab;
+        if det.abs() < f64::EPSILON {
+            break;
+        }
+        let d_a = (h_bb * g_a - h_ab * g_b) / det;
+        let d_b = (h_aa * g_b - h_ab * g_a) / det;
+        a -= d_a;
+        b -= d_b;
+        if d_a.abs() < 1e-9 && d_b.abs() < 1e-9 {
+            break;
+        }
+    }
+    Some((a, b))
+}
+
+// A classification printed by --porcelain and sent to --webhook.
+#[derive(Serialize)]
+struct LabelEvent<'a> {
+    path: &'a str,
+    label: &'static str,
+    score: f64,
+    classifier_score: f64,
+    file_size_score: f64,
+    pairwise_score: f64,
+    external_score: f64,
+    phash_score: f64,
+    knn_score: f64,
+    // Unix timestamp in seconds.
+    timestamp: u64,
+}
+
+// Rolling precision of the last K suggestions, each suggestion being the file the model rated
+// most likely to be deleted.
+struct PrecisionAtK {
+    k: usize,
+    hits: VecDeque<bool>,
+}
+
+impl PrecisionAtK {
+    fn new(k: usize) -> Self {
+        Self {
+            k: k.max(1),
+            hits: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, label: Label) {
+        if self.hits.len() == self.k {
+            self.hits.pop_front();
+        }
+        self.hits.push_back(label == Label::Delete);
+    }
+
+    fn precision(&self) -> f64 {
+        let hits = self.hits.iter().filter(|hit| **hit).count();
+        hits as f64 / self.hits.len().max(1) as f64
+    }
+}
+
+// The files containing each ngram and the number of occurrences, so that training only updates
+// the scores of the files sharing ngrams with the new example.
+struct NgramIndex {
+    files: HashMap<Ngram, Vec<(usize, u32)>>,
+}
+
+impl NgramIndex {
+    fn new(files: &[FileState]) -> Self {
+        let mut index: HashMap<Ngram, Vec<(usize, u32)>> = HashMap::new();
+        for (i, file) in files.iter().enumerate() {
+            let mut counts: HashMap<Ngram, u32> = HashMap::new();
+            for ngram in file.ngrams.iter().flatten() {
+                *counts.entry(*ngram).or_default() += 1;
+            }
+            for (ngram, count) in counts {
+                index.entry(ngram).or_default().push((i, count));
+            }
+        }
+        Self { files: index }
+    }
+
+    // Train on an example and update the ngram scores of the files, returning the files
+    // changed or None when every file was scored again.
+    fn train(
+        &self,
+        files: &mut [FileState],
+        classifier: &mut NaiveBayesClassifier,
+        label: Label,
+        ngrams: &[Ngram],
+    ) -> Option<Vec<usize>> {
+        // Ordered so the floating point updates are applied identically on every run.
+        let mut before: BTreeMap<Ngram, f64> = BTreeMap::new();
+        for ngram in ngrams {
+            before.insert(*ngram, classifier.ngram_log_odds(ngram));
+        }
+
+        // A prune, or a shared --hash-bits bucket, also changes the counts of ngrams the
+        // example doesn't have, so every file is scored again.
+        let prunes = classifier.prunes();
+        classifier.train(label, ngrams);
+        if classifier.prunes() != prunes || classifier.hashed() {
+            files.par_iter_mut().for_each(|file| {
+                file.ngram_score = file
+                    .ngrams
+                    .iter()
+                    .flatten()
+                    .map(|ngram| classifier.weight(ngram) * classifier.ngram_log_odds(ngram))
+                    .sum();
+            });
+            return None;
+        }
+
+        let mut touched = Vec::new();
+        for (ngram, before) in before {
+            let delta = classifier.weight(&ngram) * (classifier.ngram_log_odds(&ngram) - before);
+            for (i, count) in self.files.get(&ngram).into_iter().flatten() {
+                files[*i].ngram_score += *count as f64 * delta;
+                touched.push(*i);
+            }
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        Some(touched)
+    }
+}
+
+// The order of the files in the ranking: by score, then tie_break, then index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RankKey(f64, u64, usize);
+
+impl Eq for RankKey {}
+
+impl Ord for RankKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then(self.1.cmp(&other.1))
+            .then(self.2.cmp(&other.2))
+    }
+}
+
+impl PartialOrd for RankKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The unlabeled files of a session, best first. Rather than sorting every file after each
+// label, files are kept in a max-heap keyed by their score when pushed. The files a label
+// changes are pushed again, leaving their old entries to be skipped as stale. Every score also
+// moves with the shared norm_log_odds, by the file's ngram weight times the change, so top
+// pops entries until none could still beat the files found.
+struct Ranking {
+    heap: BinaryHeap<(RankKey, u32)>,
+    // The version of the current heap entry of each file, older entries are stale.
+    version: Vec<u32>,
+    member: Vec<bool>,
+    len: usize,
+    // Files moved to the top by --queue-preview or --resume, the last one first.
+    pinned: Vec<usize>,
+    // The range of norm_log_odds the heap keys were computed with.
+    norms: (f64, f64),
+    // The range of the ngram weights of the files.
+    weights: (f64, f64),
+}
+
+impl Ranking {
+    fn new(files: &mut [FileState], norm_log_odds: f64) -> Self {
+        let mut ranking = Self {
+            heap: BinaryHeap::new(),
+            version: vec![0; files.len()],
+            member: vec![true; files.len()],
+            len: files.len(),
+            pinned: Vec::new(),
+            norms: (norm_log_odds, norm_log_odds),
+            weights: (0.0, 0.0),
+        };
+        ranking.rebuild(files, norm_log_odds);
+        ranking
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The files in no particular order.
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.member.len()).filter(|i| self.member[*i])
+    }
+
+    fn push(&mut self, files: &mut [FileState], i: usize, norm_log_odds: f64) {
+        files[i].update(norm_log_odds);
+        self.version[i] = self.version[i].wrapping_add(1);
+        let file = &files[i];
+        self.heap
+            .push((RankKey(file.score, file.tie_break, i), self.version[i]));
+        self.norms = (
+            self.norms.0.min(norm_log_odds),
+            self.norms.1.max(norm_log_odds),
+        );
+    }
+
+    fn insert(&mut self, files: &mut [FileState], i: usize, norm_log_odds: f64) {
+        if !self.member[i] {
+            self.member[i] = true;
+            self.len += 1;
+        }
+        self.push(files, i, norm_log_odds);
+    }
+
+    fn remove(&mut self, i: usize) {
+        if self.member[i] {
+            self.member[i] = false;
+            self.len -= 1;
+            self.pinned.retain(|j| *j != i);
+        }
+    }
+
+    // Move a file to the top, ahead of the files by score.
+    fn pin(&mut self, i: usize) {
+        if self.member[i] {
+            self.pinned.retain(|j| *j != i);
+            self.pinned.push(i);
+        }
+    }
+
+    // Push the files whose scores changed again.
+    fn touch(&mut self, files: &mut [FileState], touched: &[usize], norm_log_odds: f64) {
+        for i in touched {
+            if self.member[*i] {
+                self.push(files, *i, norm_log_odds);
+            }
+        }
+        // Mostly stale entries, compact them.
+        if self.heap.len() > 2 * self.len + 1024 {
+            self.rebuild(files, norm_log_odds);
+        }
+    }
+
+    // Score every file again, after a change to the scores of most of them.
+    fn rebuild(&mut self, files: &mut [FileState], norm_log_odds: f64) {
+        let mut entries = Vec::with_capacity(self.len);
+        for (i, file) in files.iter_mut().enumerate() {
+            if self.member[i] {
+                file.update(norm_log_odds);
+                self.version[i] = self.version[i].wrapping_add(1);
+                entries.push((RankKey(file.score, file.tie_break, i), self.version[i]));
+            }
+        }
+        self.heap = BinaryHeap::from(entries);
+        self.norms = (norm_log_odds, norm_log_odds);
+        self.weights = files.iter().fold((f64::INFINITY, 0.0), |(lo, hi), file| {
+            (file.ngram_weight.min(lo), file.ngram_weight.max(hi))
+        });
+    }
+
+    // The k best files matching a filter, best first, with their scores updated.
+    fn top(
+        &mut self,
+        files: &mut [FileState],
+        k: usize,
+        norm_log_odds: f64,
+        filter: impl Fn(&FileState) -> bool,
+    ) -> Vec<usize> {
+        let mut ret = Vec::new();
+        for i in self.pinned.clone().into_iter().rev() {
+            files[i].update(norm_log_odds);
+            if ret.len() < k && filter(&files[i]) {
+                ret.push(i);
+            }
+        }
+        let need = k - ret.len();
+
+        // How much higher a score can be than its key: the norm moved by at most d since any
+        // key was computed.
+        let d = norm_log_odds - self.norms.0;
+        let slack = if d >= 0.0 {
+            self.weights.1 * d
+        } else {
+            self.weights.0 * d
+        } + 1e-9;
+
+        // Best first.
+        let mut found: Vec<RankKey> = Vec::new();
+        let mut popped = Vec::new();
+        while let Some(&(key, version)) = self.heap.peek() {
+            let i = key.2;
+            if !self.member[i] || version != self.version[i] {
+                self.heap.pop();
+                continue;
+            }
+            if need == 0 || (found.len() >= need && key.0 + slack < found[need - 1].0) {
+                break;
+            }
+            self.heap.pop();
+            popped.push(i);
+            files[i].update(norm_log_odds);
+            let file = &files[i];
+            if !self.pinned.contains(&i) && filter(file) {
+                let key = RankKey(file.score, file.tie_break, i);
+                let at = found.partition_point(|k| *k > key);
+                found.insert(at, key);
+            }
+        }
+        let rebuild = popped.len() > 64 + self.len / 16;
+        for i in popped {
+            self.push(files, i, norm_log_odds);
+        }
+        // Many entries within the slack, start again from keys at the current norm.
+        if rebuild {
+            self.rebuild(files, norm_log_odds);
+        }
+        ret.extend(found.into_iter().take(need).map(|key| key.2));
+        ret
+    }
+
+    // Remove the k best files matching a filter, best first. The pins only last until then.
+    fn take(
+        &mut self,
+        files: &mut [FileState],
+        k: usize,
+        norm_log_odds: f64,
+        filter: impl Fn(&FileState) -> bool,
+    ) -> Vec<usize> {
+        let top = self.top(files, k, norm_log_odds, filter);
+        for i in &top {
+            self.remove(*i);
+        }
+        self.pinned.clear();
+        top
+    }
+}
+
+fn collect_files(common: &CommonArgs) -> io::Result<HashMap<PathBuf, FileMeta>> {
+    let options = WalkOptions {
+        video_exts: &common.video_exts,
+        follow_symlinks: common.follow_symlinks,
+        dedup: !common.no_dedup,
+        canonicalize: common.canonicalize,
+        strict: common.strict_walk,
+    };
+    let walk = Walk::new(&common.paths, &options);
+    if let Some(files_from) = &common.files_from {
+        if files_from.as_os_str() == "-" {
+            walk.files_from(io::stdin().lock());
+        } else {
+            walk.files_from(io::BufReader::new(File::open(files_from)?));
+        }
+    }
+    walk.collect()
+}
+
+/// The tokenizer and classifier trained on the labeled files.
+pub struct Model {
+    // The unlabeled files.
+    files: HashMap<PathBuf, FileMeta>,
+    tokenizer: Tokenizer,
+    classifier: NaiveBayesClassifier,
+    // The classifier before training, see Model::untrained.
+    untrained: NaiveBayesClassifier,
+    delete: State,
+    keep: State,
+}
+
+// An untrained classifier with the directory priors and numeric model of the options, which are
+// learned over all files, labeled or not.
+fn new_classifier(
+    common: &CommonArgs,
+    tokenizer: &Tokenizer,
+    files: &HashMap<PathBuf, FileMeta>,
+) -> NaiveBayesClassifier {
+    let mut classifier = NaiveBayesClassifier::new(tokenizer);
+    classifier.set_pruning(common.prune_below, common.max_model_ngrams);
+    if let Some(bits) = common.hash_bits {
+        classifier.set_hash_bits(bits);
+    }
+    if let Some(depth) = common.dir_prior_depth {
+        // The roots as the directory walk names the files below them, see --canonicalize.
+        let roots: Vec<PathBuf> = common
+            .paths
+            .iter()
+            .map(|root| match std::fs::canonicalize(root) {
+                Ok(canonical) if common.canonicalize => canonical,
+                _ => root.clone(),
+            })
+            .collect();
+        classifier.set_dir_priors(DirPriors::new(&roots, depth, common.dir_prior_strength));
+    }
+    if common.learn_numeric {
+        classifier.set_numeric(NumericModel::new(
+            files,
+            common.numeric_bins,
+            common.numeric_dir_size,
+            common.age_from,
+        ));
+    }
+    classifier
+}
+
+impl Model {
+    /// Walk the library and train on the delete and keep label files of the options.
+    pub fn new(options: &Options) -> io::Result<Self> {
+        Self::load(&options.common)
+    }
+
+    pub(crate) fn load(common: &CommonArgs) -> io::Result<Self> {
+        let delete = State::load_args(&common.delete, common)?;
+        let keep = State::load_args(&common.keep, common)?;
+
+        let mut files = time_it!("walk", collect_files(common))?;
+        // Without library paths, for example with score --stdin, the vocabulary is built from
+        // the labeled files.
+        if common.paths.is_empty() && common.files_from.is_none() {
+            for path in delete.iter().chain(keep.iter()) {
+                let meta = std::fs::metadata(&path)
+                    .map(|m| FileMeta::new(&m))
+                    .unwrap_or_default();
+                files.insert(path, meta);
+            }
+        }
+        if files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no video files found and no labeled files",
+            ));
+        }
+
+        let cache = match &common.cache {
+            Some(path) => Cache::load(path, &Tokenizer::cache_key(common)),
+            None => Cache::default(),
+        };
+
+        let mut tokenizer = time_it!("tokenize", Tokenizer::new(common, &files, cache))?;
+        tokenizer.save_cache()?;
+        let untrained = new_classifier(common, &tokenizer, &files);
+        let mut classifier = untrained.clone();
+
+        let now = now_secs();
+        let bar = progress::bar(delete.contents.len() + keep.contents.len(), "Training");
+        time_it!("train", {
+            for path in delete.iter() {
+                let ngrams = tokenizer.ngrams_cached(&path);
+                let weight = delete.weight(&path, common.label_half_life, now);
+                classifier.train_weighted(Label::Delete, &ngrams, weight);
+                classifier.train_meta(Label::Delete, &path);
+                files.remove(&path);
+                bar.inc(1);
+            }
+
+            for path in keep.iter() {
+                let ngrams = tokenizer.ngrams_cached(&path);
+                let weight = keep.weight(&path, common.label_half_life, now);
+                classifier.train_weighted(Label::Keep, &ngrams, weight);
+                classifier.train_meta(Label::Keep, &path);
+                files.remove(&path);
+                bar.inc(1);
+            }
+        });
+
+        Ok(Self {
+            files,
+            tokenizer,
+            classifier,
+            untrained,
+            delete,
+            keep,
+        })
+    }
+
+    // An untrained classifier configured like the trained one, for training on other or partial
+    // label sets.
+    fn untrained(&self) -> NaiveBayesClassifier {
+        self.untrained.clone()
+    }
+
+    /// Train on a newly labeled file, in memory only, see [`Model::record`].
+    pub fn train(&mut self, label: Label, path: &Path) {
+        let ngrams = self.tokenizer.ngrams_cached(path);
+        self.classifier.train(label, &ngrams);
+        self.classifier.train_meta(label, path);
+        self.files.remove(path);
+    }
+
+    /// Append a label to the delete or keep file.
+    pub fn record(&mut self, label: Label, path: &Path) -> io::Result<()> {
+        let state = match label {
+            Label::Delete => &mut self.delete,
+            Label::Keep => &mut self.keep,
+        };
+        state.update(&path.to_string_lossy())
+    }
+
+    /// The label of a file, None if it is unlabeled.
+    pub fn label(&self, path: &Path) -> Option<Label> {
+        let entry = path.to_string_lossy();
+        [(Label::Delete, &self.delete), (Label::Keep, &self.keep)]
+            .into_iter()
+            .find(|(_, state)| state.contents.iter().any(|e| *e == entry))
+            .map(|(label, _)| label)
+    }
+
+    /// The unlabeled files.
+    pub fn candidates(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    /// The classifier score of any file, labeled or not, higher for likely deletes.
+    pub fn score(&self, path: &Path) -> f64 {
+        let ngrams = self.tokenizer.ngrams_cached(path);
+        // The seed only orders equal scores, which a single score doesn't need.
+        let mut file = FileState::new(path.to_owned(), ngrams, 0, None, 0);
+        file.init(&self.classifier, &self.tokenizer);
+        file.update(self.classifier.norm_log_odds());
+        file.score
+    }
+}
+
+fn init_logger(common: &CommonArgs) -> io::Result<()> {
+    let mut builder = env_logger::Builder::from_default_env();
+    if let Some(path) = &common.log_file {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        builder
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .write_style(env_logger::WriteStyle::Never);
+    }
+    if common.log_format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": buf.timestamp().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+    Ok(())
+}
+
+/// The command line tool, parsing the process arguments.
+pub fn run() -> io::Result<()> {
+    let args = Args::parse_from(config::args()?);
+    // Scoring paths from stdin and migrating or syncing the label files need no library.
+    let no_library = matches!(
+        &args.command,
+        Some(Command::Score(score::ScoreArgs { stdin: true, .. }))
+            | Some(Command::Migrate(_))
+            | Some(Command::Sync(_))
+    );
+    if args.common().paths.is_empty() && args.common().files_from.is_none() && !no_library {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the library [PATHS] or --files-from are required",
+            )
+            .exit();
+    }
+
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", &args.common().log_level);
+    }
+    init_logger(args.common())?;
+
+    info!("{:#?}", args);
+
+    let ret = match &args.command {
+        Some(Command::Dupes(dupes_args)) => dupes::run(dupes_args),
+        Some(Command::Report(report_args)) => report::run(report_args),
+        Some(Command::Stats(stats_args)) => stats::run(stats_args),
+        Some(Command::Evaluate(evaluate_args)) => evaluate::run(evaluate_args),
+        Some(Command::Simulate(simulate_args)) => simulate::run(simulate_args),
+        Some(Command::Diff(diff_args)) => diff::run(diff_args),
+        Some(Command::Score(score_args)) => score::run(score_args),
+        Some(Command::List(list_args)) => list::run(list_args),
+        Some(Command::Export(export_args)) => export::run(export_args),
+        Some(Command::ExportFeatures(features_args)) => export::run_features(features_args),
+        Some(Command::Thumbs(thumbs_args)) => thumbs::run(thumbs_args),
+        Some(Command::Similar(similar_args)) => similar::run(similar_args),
+        Some(Command::Generate(generate_args)) => generate::run(generate_args),
+        Some(Command::ServeApi(serve_args)) => serve::run(serve_args),
+        Some(Command::Migrate(migrate_args)) => migrate::run(migrate_args),
+        Some(Command::Sync(sync_args)) => sync::run(sync_args),
+        None => match args.mode {
+            Mode::Label => build(&args),
+            Mode::Pairwise => pairwise::run(&args),
+        },
+    };
+    if let Some(path) = &args.common().profile_out {
+        profile::write(path)?;
+    }
+    ret
+}
+
+// Raise the pHash score of the files similar to a file labeled delete.
+fn update_phash_scores(
+    args: &Args,
+    phashes: &HashMap<PathBuf, Vec<u64>>,
+    files: &mut [FileState],
+    deleted: &Path,
+) {
+    let (Some(weight), Some(deleted)) = (args.phash_weight, phashes.get(deleted)) else {
+        return;
+    };
+    for file in files.iter_mut() {
+        if let Some(hash) = phashes.get(&file.path) {
+            let similarity = phash::similarity(deleted, hash);
+            if similarity >= args.phash_threshold {
+                file.phash_score = file.phash_score.max(weight * similarity);
+            }
+        }
+    }
+}
+
+// Add labeled files to the nearest neighbors of every file, for the kNN text model. The ngram
+// set of each file is built once per call, so the labels at startup are added in one call.
+fn update_knn_scores(
+    args: &Args,
+    tokenizer: &Tokenizer,
+    files: &mut [FileState],
+    labeled: &[(Vec<Ngram>, Label)],
+) {
+    let labeled: Vec<(Vec<Ngram>, Label)> = labeled
+        .iter()
+        .map(|(ngrams, label)| (knn::ngram_set(ngrams), *label))
+        .collect();
+    files.par_iter_mut().for_each(|file| {
+        let ngrams = knn::ngram_set(&file.ngrams(tokenizer));
+        for (labeled, label) in &labeled {
+            let similarity = knn::similarity(args.knn_similarity, &ngrams, labeled);
+            file.knn.insert(args.knn_k, similarity, *label);
+        }
+        file.knn_score = args.knn_weight * file.knn.score();
+    });
+}
+
+// List the labels held by --confirm-batch and read the numbers of any to veto. Confirmed
+// labels are moved to confirmed and the vetoed files returned to rank again. A closed stdin
+// discards them and ends the session like Ctrl-C.
+fn confirm_labels(
+    files_vec: &[FileState],
+    pending: &mut Vec<(usize, Label)>,
+    confirmed: &mut VecDeque<(usize, Label)>,
+) -> io::Result<Vec<usize>> {
+    let mut stdin = io::stdin().lock();
+    println!();
+    for (n, (i, label)) in pending.iter().enumerate() {
+        println!(
+            "{:>3}  {:<6}  {:?}",
+            n + 1,
+            label.as_str(),
+            files_vec[*i].path
+        );
+    }
+    let vetoed: HashSet<usize> = loop {
+        print!("[enter] write these labels, or the numbers to veto: ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            return Ok(Vec::new());
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Ok(Vec::new());
+        }
+        let vetoed: Result<HashSet<usize>, _> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse::<usize>())
+            .collect();
+        match vetoed {
+            Ok(vetoed) if vetoed.iter().all(|n| (1..=pending.len()).contains(n)) => break vetoed,
+            _ => println!("Expected numbers from 1 to {}", pending.len()),
+        }
+    };
+    let mut ret = Vec::new();
+    for (n, (i, label)) in pending.drain(..).enumerate() {
+        if vetoed.contains(&(n + 1)) {
+            ret.push(i);
+        } else {
+            confirmed.push_back((i, label));
+        }
+    }
+    Ok(ret)
+}
+
+// Read the label of a file for --no-player, None to skip it. A closed stdin ends the session
+// like Ctrl-C.
+fn ask_label(file_name: &str) -> io::Result<Option<Label>> {
+    let mut stdin = io::stdin().lock();
+    loop {
+        print!("{}: [y] delete, [n] keep or [s] skip? ", file_name);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            return Ok(None);
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        match line.trim() {
+            "y" => return Ok(Some(Label::Delete)),
+            "n" => return Ok(Some(Label::Keep)),
+            "s" => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+// List the k best ranked candidates and read commands reordering or dropping them until an
+// empty line, or a file labeled from its name alone to train on without playing it.
+fn queue_preview(
+    args: &Args,
+    files_vec: &mut [FileState],
+    ranking: &mut Ranking,
+    norm_log_odds: f64,
+    dropped: &mut Vec<usize>,
+    k: usize,
+) -> io::Result<Option<(usize, Label)>> {
+    let mut stdin = io::stdin().lock();
+    loop {
+        let shown = ranking.top(files_vec, k, norm_log_odds, |file| match &args.query {
+            Some(query) => query.matches(&file.fields()),
+            None => true,
+        });
+        if shown.is_empty() {
+            return Ok(None);
+        }
+        println!();
+        for (n, i) in shown.iter().enumerate() {
+            let file = &files_vec[*i];
+            println!("{:>3}  {:>9.3}  {:?}", n + 1, file.score, file.path);
+        }
+        print!("[enter] play, d<n> delete, k<n> keep, t<n> play first, x<n> drop: ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 || INTERRUPTED.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let mut chars = line.chars();
+        let command = chars.next().unwrap();
+        let Some(i) = chars
+            .as_str()
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| shown.get(n.wrapping_sub(1)))
+            .cloned()
+        else {
+            println!("Expected a command and a number from 1 to {}", shown.len());
+            continue;
+        };
+        match command {
+            'd' => return Ok(Some((i, Label::Delete))),
+            'k' => return Ok(Some((i, Label::Keep))),
+            't' => ranking.pin(i),
+            'x' => {
+                ranking.remove(i);
+                dropped.push(i);
+            }
+            _ => println!("Unknown command {:?}", command),
+        }
+    }
+}
+
+// Put an interrupted file and the rest of its batch back in the ranking.
+fn requeue(
+    ranking: &mut Ranking,
+    files_vec: &mut [FileState],
+    norm_log_odds: f64,
+    batch: &mut VecDeque<usize>,
+    file_index: usize,
+) {
+    for i in batch.drain(..).chain([file_index]) {
+        ranking.insert(files_vec, i, norm_log_odds);
+    }
+}
+
+fn build(args: &Args) -> io::Result<()> {
+    let common = &args.common;
+
+    let Model {
+        files,
+        mut tokenizer,
+        mut classifier,
+        untrained,
+        mut delete,
+        mut keep,
+    } = Model::load(common)?;
+    // The kNN text model alone leaves the Naive Bayes ngram counts untrained, scoring 0, but
+    // keeps the directory priors and numeric model.
+    if args.text_model == TextModel::Knn {
+        classifier = untrained;
+        for (label, state) in [(Label::Delete, &delete), (Label::Keep, &keep)] {
+            for path in state.iter() {
+                classifier.train_meta(label, &path);
+            }
+        }
+    }
+
+    let strengths = pairwise::Comparisons::from(&args.comparisons)?.strengths();
+
+    let bar = progress::bar(files.len(), "Scoring");
+    let mut files_vec: Vec<FileState> = time_it!(
+        "score",
+        files
+            .into_par_iter()
+            .progress_with(bar)
+            .map(|(path, meta)| {
+                let ngrams = tokenizer.ngrams_cached(&path);
+                let mut file = FileState::new(
+                    path,
+                    ngrams,
+                    meta.size,
+                    args.file_size_log_base,
+                    common.seed,
+                );
+                if let Some(strength) = strengths.get(&file.path) {
+                    file.pairwise_score = args.pairwise_weight * strength;
+                }
+                file.init(&classifier, &tokenizer);
+                if args.low_memory {
+                    file.ngrams = None;
+                }
+                file
+            })
+            .collect()
+    );
+    // The file states own the only copy of the ngrams. With --low-memory they drop theirs too
+    // and the ngrams are tokenized again on demand.
+    tokenizer.release_file_ngrams();
+
+    if let Some(command) = &args.external_classifier {
+        let paths: Vec<&Path> = files_vec.iter().map(|f| f.path.as_path()).collect();
+        let scores = external::scores(command, &paths)?;
+        for file in files_vec.iter_mut() {
+            if let Some(score) = scores.get(&file.path) {
+                file.external_score = args.external_weight * score;
+            }
+        }
+    }
+    if let Some(path) = &args.scores_file {
+        let scores = external::scores_file(path)?;
+        for file in files_vec.iter_mut() {
+            if let Some(score) = scores.get(&file.path) {
+                file.external_score += args.scores_weight * score;
+            }
+        }
+    }
+
+    // Perceptual hashes of the candidates and the files labeled delete, for --phash-weight.
+    let phashes = match args.phash_weight {
+        Some(_) => {
+            let mut paths: Vec<PathBuf> = files_vec.iter().map(|f| f.path.clone()).collect();
+            paths.extend(delete.iter());
+            phash::hashes(common, &paths)?
+        }
+        None => HashMap::new(),
+    };
+    for path in delete.iter() {
+        update_phash_scores(args, &phashes, &mut files_vec, &path);
+    }
+
+    if args.text_model != TextModel::Bayes {
+        let mut labeled = Vec::new();
+        for (label, state) in [(Label::Delete, &delete), (Label::Keep, &keep)] {
+            for path in state.iter() {
+                labeled.push((tokenizer.ngrams_cached(&path), label));
+            }
+        }
+        update_knn_scores(args, &tokenizer, &mut files_vec, &labeled);
+    }
+
+    let index = (!args.low_memory).then(|| NgramIndex::new(&files_vec));
+
+    if args.dry_run {
+        let norm_log_odds = classifier.norm_log_odds();
+        files_vec
+            .par_iter_mut()
+            .for_each(|file| file.update(norm_log_odds));
+        let mut order: Vec<usize> = (0..files_vec.len()).collect();
+        order.sort_by(|a, b| {
+            let (a, b) = (&files_vec[*a], &files_vec[*b]);
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.tie_break.cmp(&a.tie_break))
+                .then_with(|| b.path.cmp(&a.path))
+        });
+        if let Some(query) = &args.query {
+            order.retain(|i| query.matches(&files_vec[*i].fields()));
+        }
+        println!(
+            "{:>5}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}  {:>10}  path",
+            "rank", "score", "file size", "pairwise", "external", "phash", "knn", "classifier"
+        );
+        for (rank, i) in order.iter().take(args.dry_run_top).enumerate() {
+            let file = &files_vec[*i];
+            println!(
+                "{:>5}  {:>9.3}  {:>9.3}  {:>9.3}  {:>9.3}  {:>9.3}  {:>9.3}  {:>10.3}  {:?}",
+                rank + 1,
+                file.score,
+                file.file_size_score,
+                file.pairwise_score,
+                file.external_score,
+                file.phash_score,
+                file.knn_score,
+                file.classifier_score,
+                file.path
+            );
+        }
+        return Ok(());
+    }
+
+    // The unclassified files, best first.
+    let mut ranking = Ranking::new(&mut files_vec, classifier.norm_log_odds());
+
+    let (mut session, mut resume_current) = match args.resume {
+        true => match ResumeState::load(&args.session_file)? {
+            Some(state) => (state.session, state.current),
+            None => {
+                warn!("No session to resume in {:?}", args.session_file);
+                (
+                    Session::new(classifier.vocab_size(), args.summary_threshold),
+                    None,
+                )
+            }
+        },
+        false => (
+            Session::new(classifier.vocab_size(), args.summary_threshold),
+            None,
+        ),
+    };
+    // The file playing when the session was interrupted.
+    let mut current = None;
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+        .expect("Failed to set Ctrl-C handler");
+
+    let mut precision = PrecisionAtK::new(args.precision_k);
+    let mut metrics = match &args.metrics {
+        Some(path) => {
+            let is_new = std::fs::metadata(path).map_or(true, |m| m.len() == 0);
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            if is_new {
+                writeln!(file, "path,label,score,precision")?;
+            }
+            Some(file)
+        }
+        None => None,
+    };
+
+    let painter = Painter::new(args.color);
+    let visualizer = ScoreVisualizer::new(if args.porcelain { Viz::Off } else { args.viz });
+    let mut vlc = VLCProcessHandle::new(&args.vlc, &common.path_map);
+    #[allow(unused_mut)]
+    let mut notifier = Notifier::new(args.webhook.as_deref());
+    #[cfg(feature = "mqtt")]
+    if let Some(url) = &args.mqtt {
+        notifier = notifier.with_mqtt(url, &args.mqtt_topic)?;
+    }
+    // The files queued in the VLC playlist after the current one, best first, and whether VLC
+    // has already moved on to the first of them.
+    let mut batch: VecDeque<usize> = VecDeque::new();
+    let mut playing = false;
+    // The file queued after the current one by --prebuffer.
+    let mut prebuffered: Option<usize> = None;
+
+    let started = std::time::Instant::now();
+    let mut labeled = 0;
+    let mut prev_top: Vec<usize> = Vec::new();
+    let mut stable = 0;
+    // The classifier score of each labeled file before training on it.
+    let mut predictions: Vec<(f64, Label)> = Vec::new();
+
+    // Files skipped by --classify-timeout, played again once the others have been.
+    let mut deferred: Vec<usize> = Vec::new();
+    // Files dropped from the session in the --queue-preview.
+    let mut dropped: Vec<usize> = Vec::new();
+    // The labels of the current batch held by --confirm-batch, and the confirmed ones waiting
+    // to be applied.
+    let mut pending: Vec<(usize, Label)> = Vec::new();
+    let mut confirmed: VecDeque<(usize, Label)> = VecDeque::new();
+
+    while (!ranking.is_empty()
+        || !batch.is_empty()
+        || !deferred.is_empty()
+        || !pending.is_empty()
+        || !confirmed.is_empty())
+        && !INTERRUPTED.load(Ordering::SeqCst)
+    {
+        let max_labels = args
+            .max_labels
+            .is_some_and(|max| labeled + pending.len() >= max);
+        let max_minutes = args
+            .max_minutes
+            .is_some_and(|max| started.elapsed().as_secs_f64() >= max * 60.0);
+        // Labels held by --confirm-batch are confirmed at the end of each batch and before
+        // the session ends, then applied one per iteration.
+        if !pending.is_empty()
+            && confirmed.is_empty()
+            && (batch.is_empty() || max_labels || max_minutes)
+        {
+            let norm_log_odds = classifier.norm_log_odds();
+            for i in confirm_labels(&files_vec, &mut pending, &mut confirmed)? {
+                ranking.insert(&mut files_vec, i, norm_log_odds);
+            }
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        if confirmed.is_empty() && max_labels {
+            info!("Reached --max-labels");
+            break;
+        }
+        if confirmed.is_empty() && max_minutes {
+            info!("Reached --max-minutes");
+            break;
+        }
+
+        let norm_log_odds = classifier.norm_log_odds();
+        if ranking.is_empty() && batch.is_empty() {
+            for i in deferred.drain(..) {
+                ranking.insert(&mut files_vec, i, norm_log_odds);
+            }
+        }
+        for i in &batch {
+            files_vec[*i].update(norm_log_odds);
+        }
+
+        if let Some(min_tau) = args.stable_tau {
+            let top = ranking.top(&mut files_vec, args.stable_top, norm_log_odds, |_| true);
+            match kendall_tau(&prev_top, &top) {
+                Some(tau) if tau >= min_tau => stable += 1,
+                _ => stable = 0,
+            }
+            prev_top = top;
+            if stable >= args.stable_patience && pending.is_empty() && confirmed.is_empty() {
+                info!("Ranking stable for {} iterations", stable);
+                break;
+            }
+        }
+
+        // VLC moves on to the next playlist item by itself when the current file is skipped, so
+        // the batch is only reordered when it is loaded again.
+        if args.rerank_batch && !playing && batch.len() > 1 {
+            batch.make_contiguous().sort_by(|a, b| {
+                let (a, b) = (&files_vec[*a], &files_vec[*b]);
+                b.score
+                    .total_cmp(&a.score)
+                    .then_with(|| b.tie_break.cmp(&a.tie_break))
+                    .then_with(|| b.path.cmp(&a.path))
+            });
+        }
+
+        // A resumed session starts with the file that was interrupted.
+        if let Some(path) = resume_current.take() {
+            let found = ranking.iter().find(|i| files_vec[*i].path == path);
+            if let Some(i) = found {
+                ranking.pin(i);
+            }
+        }
+
+        let confirmed_label = confirmed.pop_front();
+        let confirming = confirmed_label.is_some();
+        // The label of a file labeled from its name or confirmed, applied without VLC.
+        let mut named = confirmed_label.map(|(_, label)| label);
+        if !confirming && batch.is_empty() {
+            let previewed = match args.queue_preview {
+                0 => None,
+                k => queue_preview(
+                    args,
+                    &mut files_vec,
+                    &mut ranking,
+                    norm_log_odds,
+                    &mut dropped,
+                    k,
+                )?,
+            };
+            match (previewed, &args.query) {
+                (Some((i, label)), _) => {
+                    ranking.remove(i);
+                    batch.push_back(i);
+                    named = Some(label);
+                }
+                (None, Some(query)) => {
+                    // The best files matching --where at their current scores.
+                    batch = ranking
+                        .take(&mut files_vec, args.batch.max(1), norm_log_odds, |file| {
+                            query.matches(&file.fields())
+                        })
+                        .into();
+                    if batch.is_empty() {
+                        info!("No files match --where");
+                        break;
+                    }
+                }
+                (None, None) => {
+                    if ranking.is_empty() {
+                        continue;
+                    }
+                    batch = ranking
+                        .take(&mut files_vec, args.batch.max(1), norm_log_odds, |_| true)
+                        .into();
+                }
+            }
+            playing = false;
+        }
+        let file_index = match confirmed_label {
+            Some((i, _)) => i,
+            None => batch.pop_front().unwrap(),
+        };
+        files_vec[file_index].update(norm_log_odds);
+        let queued_next = prebuffered.take() == Some(file_index);
+        if args.prebuffer && batch.is_empty() {
+            // The best file after this one at the current scores, likely still the best after
+            // training on the label.
+            prebuffered = ranking
+                .top(&mut files_vec, 1, norm_log_odds, |file| match &args.query {
+                    Some(query) => query.matches(&file.fields()),
+                    None => true,
+                })
+                .first()
+                .cloned();
+        }
+        let file_state = &files_vec[file_index];
+
+        // The position by score of a file among the remaining candidates, 1 for the best.
+        let candidates = ranking.len() + batch.len() + 1;
+        let rank_of = |i: usize| {
+            let score = files_vec[i].score_at(norm_log_odds);
+            let better = ranking
+                .iter()
+                .chain(batch.iter().cloned())
+                .chain([file_index])
+                .filter(|j| files_vec[*j].score_at(norm_log_odds) > score)
+                .count();
+            better + 1
+        };
+        let rank = rank_of(file_index);
+        // A label confirmed by --confirm-batch was shown when its file played.
+        if !confirming {
+            if !args.porcelain {
+                println!();
+            }
+            // All candidates, including the rest of the batch, by ascending total score.
+            let mut shown: Vec<(f64, f64, f64)> = Vec::new();
+            if !args.porcelain && args.viz != Viz::Off {
+                shown = ranking
+                    .iter()
+                    .chain(batch.iter().cloned())
+                    .chain([file_index])
+                    .map(|i| {
+                        let file = &files_vec[i];
+                        (
+                            file.score_at(norm_log_odds),
+                            file.file_size_score,
+                            file.classifier_score_at(norm_log_odds),
+                        )
+                    })
+                    .collect();
+                shown.sort_by(|a, b| a.0.total_cmp(&b.0));
+            }
+            let column =
+                |f: fn(&(f64, f64, f64)) -> f64| -> Vec<f64> { shown.iter().map(f).collect() };
+            visualizer.plot("File size", &column(|f| f.1), file_state.file_size_score);
+            visualizer.plot("Classifier", &column(|f| f.2), file_state.classifier_score);
+            if args.viz == Viz::Histogram {
+                visualizer.plot("Total", &column(|f| f.0), file_state.score);
+            }
+
+            if !args.porcelain {
+                file_state.debug(&tokenizer, &classifier, &painter);
+            }
+
+            // Calibrated against the earlier predictions of this session when possible, otherwise
+            // the raw Naive Bayes posterior. The file size score is ignored.
+            let (a, b) = platt_scaling(&predictions).unwrap_or((1.0, 0.0));
+            let p_delete = 1.0 / (1.0 + (-(a * file_state.classifier_score + b)).exp());
+            let (predicted, confidence) = if p_delete > 0.5 {
+                (Label::Delete, p_delete)
+            } else {
+                (Label::Keep, 1.0 - p_delete)
+            };
+            if !args.porcelain {
+                let signed = match predicted {
+                    Label::Delete => confidence,
+                    Label::Keep => -confidence,
+                };
+                println!(
+                    "Predicted {}, {:.0}% (rank {} of {}, {:.0}th percentile)",
+                    painter.paint_sign(signed, predicted.as_str().to_uppercase()),
+                    confidence * 100.0,
+                    rank,
+                    candidates,
+                    100.0 * (candidates - rank + 1) as f64 / candidates as f64
+                );
+            }
+            notifier.send(
+                "score",
+                &serde_json::json!({
+                    "path": file_state.path,
+                    "score": round(file_state.score),
+                    "predicted": predicted.as_str(),
+                    "confidence": round(confidence),
+                    "rank": rank,
+                    "candidates": candidates,
+                }),
+            );
+        }
+
+        let file_name = file_state
+            .path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+
+        let path_str = file_state.path.to_string_lossy().to_string();
+        let file_started = std::time::Instant::now();
+        let score = file_state.score;
+        let classifier_score = file_state.classifier_score;
+        let file_size_score = file_state.file_size_score;
+        let pairwise_score = file_state.pairwise_score;
+        let external_score = file_state.external_score;
+        let phash_score = file_state.phash_score;
+        let knn_score = file_state.knn_score;
+        let ngrams = file_state.ngrams(&tokenizer).into_owned();
+
+        let queued: Vec<usize> = std::iter::once(file_index)
+            .chain(batch.clone())
+            .chain(prebuffered)
+            .collect();
+        let paths: Vec<&Path> = queued
+            .iter()
+            .map(|i| files_vec[*i].path.as_path())
+            .collect();
+        let osd: Vec<String> = if args.osd {
+            queued
+                .iter()
+                .map(|i| {
+                    format!(
+                        "STOP=delete PAUSE=keep  #{}/{}  score {:.3}",
+                        rank_of(*i),
+                        candidates,
+                        files_vec[*i].score
+                    )
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut timed_out = false;
+        let label = match named {
+            Some(label) => {
+                // VLC did not open the file queued by --prebuffer.
+                prebuffered = None;
+                Some(label)
+            }
+            None if args.no_player => {
+                prebuffered = None;
+                ask_label(&file_name)?
+            }
+            None => {
+                let started = if playing {
+                    Ok(())
+                } else if queued_next {
+                    vlc.play_queued(&paths, &osd)
+                } else {
+                    vlc.play(&paths, &osd)
+                };
+                playing = false;
+                let length = match started.and_then(|_| vlc.wait_for_status()) {
+                    Ok(status) => {
+                        let found_file_name = status.file_name();
+                        if Some(&file_name) != found_file_name.as_ref() {
+                            error!(
+                                "Filename mismatch {:?} {:?}, skipping",
+                                file_name, found_file_name
+                            );
+                            continue;
+                        }
+                        status.length()
+                    }
+                    Err(e) => {
+                        error!("Vlc startup error {:?}", e);
+                        // Ctrl-C also stops the player, leaving this file to --resume.
+                        if INTERRUPTED.load(Ordering::SeqCst) {
+                            requeue(
+                                &mut ranking,
+                                &mut files_vec,
+                                norm_log_odds,
+                                &mut batch,
+                                file_index,
+                            );
+                            current = Some(files_vec[file_index].path.clone());
+                        }
+                        continue;
+                    }
+                };
+
+                if let Some(start) = &args.preview_start {
+                    if let Err(e) = vlc.seek(start.seconds(length)) {
+                        error!("Seek error {:?}", e);
+                    }
+                }
+                let preview_end = args.preview_length.map(|preview| {
+                    std::time::Instant::now()
+                        + std::time::Duration::from_secs_f64(preview.seconds(length))
+                });
+                let timeout_end = args
+                    .classify_timeout
+                    .map(|secs| std::time::Instant::now() + std::time::Duration::from_secs(secs));
+
+                // The furthest playback position seen, as a fraction of the length.
+                let mut watched: f64 = 0.0;
+                let mut restarts = 0;
+                let label = loop {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+
+                    if INTERRUPTED.load(Ordering::SeqCst) {
+                        break None;
+                    }
+
+                    if preview_end.is_some_and(|end| std::time::Instant::now() >= end) {
+                        info!("Preview ended, skipping");
+                        break None;
+                    }
+
+                    if timeout_end.is_some_and(|end| std::time::Instant::now() >= end) {
+                        info!(
+                            "No label after {}s, skipping",
+                            args.classify_timeout.unwrap()
+                        );
+                        if let Err(e) = vlc.stop() {
+                            error!("Stop error {:?}", e);
+                        }
+                        timed_out = true;
+                        break None;
+                    }
+
+                    let status = match vlc.status() {
+                        Ok(status) => {
+                            debug!("{:?}", status);
+                            status
+                        }
+                        Err(e) => match vlc.exit_status() {
+                            Some(exit) if !exit.success() => {
+                                if restarts >= args.vlc.vlc_restarts {
+                                    error!(
+                                        "VLC crashed ({}) {} times, skipping {:?}",
+                                        exit,
+                                        restarts + 1,
+                                        path_str
+                                    );
+                                    break None;
+                                }
+                                restarts += 1;
+                                warn!(
+                                    "VLC crashed ({}), restarting playback {}/{}",
+                                    exit, restarts, args.vlc.vlc_restarts
+                                );
+                                if let Err(e) =
+                                    vlc.play(&paths, &osd).and_then(|_| vlc.wait_for_status())
+                                {
+                                    error!("Vlc restart error {:?}", e);
+                                    break None;
+                                }
+                                if let Err(e) = vlc.seek(watched * length) {
+                                    error!("Seek error {:?}", e);
+                                }
+                                continue;
+                            }
+                            Some(_)
+                                if args.delete_before.is_some_and(|before| watched < before) =>
+                            {
+                                info!("Closed at {:.0}%", watched * 100.0);
+                                break Some(Label::Delete);
+                            }
+                            Some(_) => {
+                                info!("VLC closed, skipping");
+                                break None;
+                            }
+                            None => {
+                                error!("Status error: {:?}", e);
+                                break None;
+                            }
+                        },
+                    };
+
+                    let found_file_name = status.file_name();
+                    if found_file_name.is_some() && found_file_name.as_ref() != Some(&file_name) {
+                        info!("Skipped to the next file");
+                        // VLC is already past a prebuffered file, so it is loaded again if it is next.
+                        prebuffered = None;
+                        playing = batch.front().is_some_and(|i| {
+                            files_vec[*i]
+                                .path
+                                .file_name()
+                                .map(|n| n.to_string_lossy().to_string())
+                                == found_file_name
+                        });
+                        break Some(Label::Keep);
+                    }
+
+                    watched = watched.max(status.position());
+                    if args.keep_past.is_some_and(|past| watched >= past) {
+                        info!("Watched past {:.0}%", watched * 100.0);
+                        break Some(Label::Keep);
+                    }
+
+                    if status.position() >= 0.99 {
+                        match args.on_end {
+                            EndAction::Replay => {}
+                            EndAction::Skip => {
+                                info!("Reached the end, skipping");
+                                break None;
+                            }
+                            EndAction::Keep => break Some(Label::Keep),
+                            EndAction::Delete => break Some(Label::Delete),
+                        }
+                    }
+
+                    match status.state() {
+                        "stopped" => break Some(Label::Delete),
+                        "paused" => break Some(Label::Keep),
+                        _ => {}
+                    }
+                };
+                label
+            }
+        };
+
+        let Some(label) = label else {
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                requeue(
+                    &mut ranking,
+                    &mut files_vec,
+                    norm_log_odds,
+                    &mut batch,
+                    file_index,
+                );
+                current = Some(files_vec[file_index].path.clone());
+            } else if timed_out && args.requeue {
+                deferred.push(file_index);
+            }
+            continue;
+        };
+
+        if args.confirm_batch && !confirming {
+            pending.push((file_index, label));
+            continue;
+        }
+
+        // The scores of the candidates before training, for --score-impact.
+        let before: Vec<(usize, f64)> = match args.score_impact > 0 && !args.porcelain {
+            true => ranking
+                .iter()
+                .map(|i| (i, files_vec[i].score_at(norm_log_odds)))
+                .collect(),
+            false => Vec::new(),
+        };
+
+        match label {
+            Label::Delete => {
+                delete.update(&path_str)?;
+                update_phash_scores(args, &phashes, &mut files_vec, Path::new(&path_str));
+            }
+            Label::Keep => keep.update(&path_str)?,
+        }
+
+        // The files whose scores changed other than through norm_log_odds, None for all.
+        let mut touched = time_it!("retrain", {
+            match (&index, args.text_model) {
+                (_, TextModel::Knn) => None,
+                (Some(index), _) => {
+                    let touched = index.train(&mut files_vec, &mut classifier, label, &ngrams);
+                    match classifier.has_meta() {
+                        true => {
+                            classifier.train_meta(label, Path::new(&path_str));
+                            files_vec.par_iter_mut().for_each(|file| {
+                                file.meta_score = classifier.meta_log_odds(&file.path);
+                            });
+                            None
+                        }
+                        false => touched,
+                    }
+                }
+                (None, _) => {
+                    classifier.train(label, &ngrams);
+                    classifier.train_meta(label, Path::new(&path_str));
+                    files_vec
+                        .par_iter_mut()
+                        .for_each(|file| file.init(&classifier, &tokenizer));
+                    None
+                }
+            }
+        });
+        if args.text_model != TextModel::Bayes {
+            update_knn_scores(args, &tokenizer, &mut files_vec, &[(ngrams, label)]);
+            touched = None;
+        }
+        if label == Label::Delete && args.phash_weight.is_some() {
+            touched = None;
+        }
+        match touched {
+            Some(touched) => ranking.touch(&mut files_vec, &touched, classifier.norm_log_odds()),
+            None => ranking.rebuild(&mut files_vec, classifier.norm_log_odds()),
+        }
+
+        info!(
+            "{:?} ({}) score {:.3} after {:.1}s",
+            path_str,
+            label.as_str().to_uppercase(),
+            score,
+            file_started.elapsed().as_secs_f64()
+        );
+
+        let event = LabelEvent {
+            path: &path_str,
+            label: label.as_str(),
+            score: round(score),
+            classifier_score: round(classifier_score),
+            file_size_score: round(file_size_score),
+            pairwise_score: round(pairwise_score),
+            external_score: round(external_score),
+            phash_score: round(phash_score),
+            knn_score: round(knn_score),
+            timestamp: now_secs(),
+        };
+        if args.porcelain {
+            println!("{}", serde_json::to_string(&event)?);
+        }
+        notifier.send("label", &event);
+
+        if args.score_impact > 0 && !args.porcelain {
+            let norm_log_odds = classifier.norm_log_odds();
+            let mut deltas: Vec<(f64, usize)> = before
+                .iter()
+                .map(|(i, before)| (files_vec[*i].score_at(norm_log_odds) - before, *i))
+                .collect();
+            deltas.sort_by(|a, b| {
+                b.0.total_cmp(&a.0)
+                    .then_with(|| files_vec[a.1].path.cmp(&files_vec[b.1].path))
+            });
+            let n = args.score_impact.min(deltas.len());
+            println!("Score impact");
+            for (delta, i) in deltas.iter().take(n).filter(|d| d.0 > 0.0) {
+                let delta = painter.paint_sign(*delta, format!("{:>+9.3}", delta));
+                println!("{}  {:?}", delta, files_vec[*i].path);
+            }
+            for (delta, i) in deltas.iter().rev().take(n).rev().filter(|d| d.0 < 0.0) {
+                let delta = painter.paint_sign(*delta, format!("{:>+9.3}", delta));
+                println!("{}  {:?}", delta, files_vec[*i].path);
+            }
+        }
+
+        labeled += 1;
+        predictions.push((classifier_score, label));
+        session.label(label);
+        precision.push(label);
+        if !args.porcelain {
+            println!(
+                "Precision@{}: {:.3} ({} labels)",
+                precision.k,
+                precision.precision(),
+                precision.hits.len()
+            );
+        }
+        if let Some(file) = &mut metrics {
+            writeln!(
+                file,
+                "\"{}\",{},{},{}",
+                path_str.replace('"', "\"\""),
+                label.as_str(),
+                round(score),
+                round(precision.precision())
+            )?;
+        }
+    }
+
+    if !pending.is_empty() || !confirmed.is_empty() {
+        warn!(
+            "Discarded {} unconfirmed labels",
+            pending.len() + confirmed.len()
+        );
+    }
+    let mut order: Vec<usize> = ranking.iter().collect();
+    order.extend(pending.drain(..).map(|(i, _)| i));
+    order.extend(confirmed.drain(..).map(|(i, _)| i));
+    order.extend(batch.drain(..));
+    order.append(&mut deferred);
+    order.append(&mut dropped);
+    let norm_log_odds = classifier.norm_log_odds();
+    for i in &order {
+        files_vec[*i].update(norm_log_odds);
+    }
+    session.finish(
+        classifier.vocab_size(),
+        order.iter().map(|i| files_vec[*i].classifier_score),
+    );
+    if !args.porcelain {
+        session.print();
+    }
+    notifier.send("session_end", &session);
+
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        ResumeState { current, session }.save(&args.session_file)?;
+    } else {
+        ResumeState::remove(&args.session_file)?;
+        if let Some(history) = &args.history {
+            session.append(history)?;
+        }
+    }
+
+    Ok(())
+}
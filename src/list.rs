@@ -0,0 +1,158 @@
+use crate::{now_secs, CommonArgs, Label, Model, Period, State};
+use clap::ValueEnum;
+use ignore::overrides::OverrideBuilder;
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Which {
+    Delete,
+    Keep,
+    All,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    /// By path.
+    Name,
+    /// By modification time, oldest first.
+    Date,
+    /// By when the file was labeled, oldest first. Files labeled before timestamps were
+    /// recorded come first.
+    Labeled,
+    /// By score, best delete candidates first. Implies --with-scores.
+    Score,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Which labeled files to list.
+    #[clap(long, default_value = "all")]
+    label: Which,
+
+    /// Print the files as JSON.
+    #[clap(long)]
+    json: bool,
+
+    #[clap(long, default_value = "name")]
+    sort: Sort,
+
+    /// Only list files matching this glob, for example "*.mkv" or "/media/shows/**".
+    #[clap(long)]
+    filter: Option<String>,
+
+    /// Score each file with the model trained on all labeled files.
+    #[clap(long)]
+    with_scores: bool,
+
+    /// Only list files labeled within this period, for example "30d" or "12h", to audit a
+    /// recent session. Files labeled before timestamps were recorded are left out.
+    #[clap(long, value_name = "PERIOD")]
+    since: Option<Period>,
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    path: PathBuf,
+    label: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+    // When the file was labeled, in seconds since the unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    labeled: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+}
+
+// List the labeled files.
+pub fn run(args: &ListArgs) -> io::Result<()> {
+    let common = &args.common;
+    let with_scores = args.with_scores || args.sort == Sort::Score;
+
+    let filter = match &args.filter {
+        Some(glob) => Some(
+            OverrideBuilder::new("/")
+                .add(glob)
+                .and_then(|builder| builder.build())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        ),
+        None => None,
+    };
+
+    let mut entries = Vec::new();
+    for (label, path) in [(Label::Delete, &common.delete), (Label::Keep, &common.keep)] {
+        match (args.label, label) {
+            (Which::All, _) | (Which::Delete, Label::Delete) | (Which::Keep, Label::Keep) => {}
+            _ => continue,
+        }
+        let state = State::from(path)?;
+        for path in state.iter() {
+            if filter
+                .as_ref()
+                .is_some_and(|filter| !filter.matched(&path, false).is_whitelist())
+            {
+                continue;
+            }
+            let labeled = state.timestamp(&path);
+            if let Some(Period(since)) = args.since {
+                let cutoff = now_secs().saturating_sub(since as u64);
+                if labeled.is_none_or(|time| time < cutoff) {
+                    continue;
+                }
+            }
+            let modified = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs());
+            entries.push(Entry {
+                path,
+                label: label.as_str(),
+                modified,
+                labeled,
+                score: None,
+            });
+        }
+    }
+
+    if with_scores {
+        let model = Model::load(common)?;
+        for entry in entries.iter_mut() {
+            entry.score = Some(model.score(&entry.path));
+        }
+    }
+
+    match args.sort {
+        Sort::Name => entries.sort_by(|a, b| a.path.cmp(&b.path)),
+        Sort::Date => entries.sort_by_key(|e| (e.modified.unwrap_or_default(), e.path.clone())),
+        Sort::Labeled => entries.sort_by_key(|e| (e.labeled, e.path.clone())),
+        Sort::Score => entries.sort_by(|a, b| {
+            let score = |e: &Entry| e.score.unwrap_or(f64::NEG_INFINITY);
+            score(b)
+                .total_cmp(&score(a))
+                .then_with(|| a.path.cmp(&b.path))
+        }),
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    for entry in &entries {
+        let mut line = String::new();
+        if let Some(score) = entry.score {
+            line += &format!("{:>9.3}  ", score);
+        }
+        if args.label == Which::All {
+            line += &format!("{:<6}  ", entry.label);
+        }
+        println!("{}{}", line, entry.path.display());
+    }
+    Ok(())
+}
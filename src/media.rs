@@ -0,0 +1,220 @@
+use crate::Error;
+use clap::ValueEnum;
+use log::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Server {
+    Jellyfin,
+    Plex,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MediaArgs {
+    /// A Jellyfin or Plex server to read watch counts, ratings and genres from, for example
+    /// http://localhost:8096. They are added to the files as tokens like <watched>,
+    /// <rating:7> and <genre:comedy>.
+    #[clap(long)]
+    media_server_url: Option<String>,
+
+    #[clap(long, default_value = "jellyfin")]
+    media_server: Server,
+
+    /// The Jellyfin API key or Plex token.
+    #[clap(long)]
+    media_server_token: Option<String>,
+
+    /// The Jellyfin user id whose watch counts and favorites are read. Without it only ratings
+    /// and genres are added.
+    #[clap(long)]
+    media_server_user: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MediaInfo {
+    // None where the server has no user data, such as Jellyfin without --media-server-user.
+    pub play_count: Option<u64>,
+    pub favorite: bool,
+    pub rating: Option<f64>,
+    pub genres: Vec<String>,
+}
+
+impl MediaInfo {
+    pub fn tokens(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+        match self.play_count {
+            Some(0) => ret.push("<unwatched>".to_string()),
+            Some(_) => ret.push("<watched>".to_string()),
+            None => {}
+        }
+        if self.favorite {
+            ret.push("<favorite>".to_string());
+        }
+        if let Some(rating) = self.rating {
+            ret.push(format!("<rating:{}>", rating.round()));
+        }
+        for genre in &self.genres {
+            ret.push(format!("<genre:{}>", genre.to_lowercase()));
+        }
+        ret
+    }
+}
+
+// The items known to the media server, matched to files by path or else by file name.
+#[derive(Debug, Default)]
+pub struct Media {
+    by_path: HashMap<PathBuf, MediaInfo>,
+    by_name: HashMap<String, Option<MediaInfo>>,
+}
+
+impl Media {
+    pub fn load(args: &MediaArgs) -> io::Result<Self> {
+        let Some(url) = &args.media_server_url else {
+            return Ok(Self::default());
+        };
+        let url = url.trim_end_matches('/');
+        let items = match args.media_server {
+            Server::Jellyfin => jellyfin(args, url),
+            Server::Plex => plex(args, url),
+        }
+        .map_err(|e| io::Error::other(format!("{}: {:?}", url, e)))?;
+        info!("Media server items: {}", items.len());
+
+        let mut media = Self::default();
+        for (path, info) in items {
+            if let Some(name) = path.file_name() {
+                let name = name.to_string_lossy().to_string();
+                // Ambiguous file names match nothing.
+                media
+                    .by_name
+                    .entry(name)
+                    .and_modify(|e| *e = None)
+                    .or_insert_with(|| Some(info.clone()));
+            }
+            media.by_path.insert(crate::walk::normalize(&path), info);
+        }
+        Ok(media)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty() && self.by_name.is_empty()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&MediaInfo> {
+        self.by_path.get(path).or_else(|| {
+            let name = path.file_name()?.to_string_lossy();
+            self.by_name.get(name.as_ref())?.as_ref()
+        })
+    }
+}
+
+fn get_json(url: &str, headers: &[(&str, &str)]) -> Result<Value, Error> {
+    let client = reqwest::blocking::Client::new();
+    let mut request = client.get(url).header("Accept", "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let text = request.send()?.error_for_status()?.text()?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+fn genres(values: Option<&Value>, f: impl Fn(&Value) -> Option<&str>) -> Vec<String> {
+    values
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(f)
+        .map(str::to_string)
+        .collect()
+}
+
+fn jellyfin(args: &MediaArgs, url: &str) -> Result<Vec<(PathBuf, MediaInfo)>, Error> {
+    let token = args.media_server_token.as_deref().unwrap_or_default();
+    let items = match &args.media_server_user {
+        Some(user) => format!("{}/Users/{}/Items", url, user),
+        None => format!("{}/Items", url),
+    };
+    let response = get_json(
+        &format!(
+            "{}?Recursive=true&IncludeItemTypes=Movie,Episode,Video&Fields=Path,Genres",
+            items
+        ),
+        &[("X-Emby-Token", token)],
+    )?;
+
+    let mut ret = Vec::new();
+    for item in response["Items"].as_array().into_iter().flatten() {
+        let Some(path) = item["Path"].as_str() else {
+            continue;
+        };
+        // Only returned for the items of a user.
+        let user_data = item.get("UserData");
+        ret.push((
+            PathBuf::from(path),
+            MediaInfo {
+                play_count: user_data.map(|u| u["PlayCount"].as_u64().unwrap_or_default()),
+                favorite: user_data
+                    .and_then(|u| u["IsFavorite"].as_bool())
+                    .unwrap_or_default(),
+                rating: item["CommunityRating"].as_f64(),
+                genres: genres(item.get("Genres"), Value::as_str),
+            },
+        ));
+    }
+    Ok(ret)
+}
+
+fn plex(args: &MediaArgs, url: &str) -> Result<Vec<(PathBuf, MediaInfo)>, Error> {
+    let token = args.media_server_token.as_deref().unwrap_or_default();
+    let headers = [("X-Plex-Token", token)];
+    let sections = get_json(&format!("{}/library/sections", url), &headers)?;
+
+    let mut ret = Vec::new();
+    for section in sections["MediaContainer"]["Directory"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        // Movies, or the episodes of shows.
+        let kind = match section["type"].as_str() {
+            Some("movie") => 1,
+            Some("show") => 4,
+            _ => continue,
+        };
+        let Some(key) = section["key"].as_str() else {
+            continue;
+        };
+        let items = get_json(
+            &format!("{}/library/sections/{}/all?type={}", url, key, kind),
+            &headers,
+        )?;
+        for item in items["MediaContainer"]["Metadata"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let info = MediaInfo {
+                // Left out of unwatched items.
+                play_count: Some(item["viewCount"].as_u64().unwrap_or_default()),
+                favorite: false,
+                rating: item["userRating"]
+                    .as_f64()
+                    .or_else(|| item["audienceRating"].as_f64())
+                    .or_else(|| item["rating"].as_f64()),
+                genres: genres(item.get("Genre"), |g| g["tag"].as_str()),
+            };
+            for media in item["Media"].as_array().into_iter().flatten() {
+                for part in media["Part"].as_array().into_iter().flatten() {
+                    if let Some(file) = part["file"].as_str() {
+                        ret.push((PathBuf::from(file), info.clone()));
+                    }
+                }
+            }
+        }
+    }
+    Ok(ret)
+}
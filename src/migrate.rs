@@ -0,0 +1,153 @@
+use crate::header::{Header, VERSION};
+use crate::{normalize_entry, CommonArgs};
+use log::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MigrateArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Print what would change without writing the label files.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+fn times_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(".times");
+    PathBuf::from(path)
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    match File::open(path) {
+        Ok(file) => BufReader::new(file).lines().collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// An entry as it is written from now on: absolute, without a carriage return from a file
+// edited on Windows, and in the form the directory walk names it.
+fn normalize(entry: &str) -> io::Result<String> {
+    let entry = entry.trim_end_matches('\r');
+    let path = std::path::absolute(entry)?;
+    Ok(normalize_entry(path.to_string_lossy().into_owned()))
+}
+
+// Write lines to a file through a temporary file, keeping the old file as <file>.bak.
+pub fn replace(path: &Path, lines: &[String]) -> io::Result<()> {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.into_inner()?.sync_all()?;
+    if path.exists() {
+        let mut bak = path.to_path_buf().into_os_string();
+        bak.push(".bak");
+        std::fs::copy(path, bak)?;
+    }
+    std::fs::rename(tmp, path)
+}
+
+// Upgrade one label file and its timestamps sidecar, returning whether anything changed.
+fn migrate(path: &Path, common: &CommonArgs, dry_run: bool) -> io::Result<bool> {
+    let lines = read_lines(path)?;
+    if lines.is_empty() {
+        return Ok(false);
+    }
+    let mut changes: Vec<String> = Vec::new();
+
+    let header = match Header::parse(lines.iter().map(String::as_str)) {
+        Some(header) if header.version == VERSION => header,
+        Some(mut header) => {
+            changes.push(format!("header version {} -> {}", header.version, VERSION));
+            header.version = VERSION;
+            header
+        }
+        None => {
+            let header = Header::new(common);
+            changes.push(format!("add header {:?}", header.lines()[0]));
+            header
+        }
+    };
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut entries: Vec<String> = Vec::new();
+    // Old entry to new entry, to rewrite the timestamps.
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    for line in lines
+        .iter()
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+    {
+        let entry = normalize(line)?;
+        if &entry != line {
+            changes.push(format!("{:?} -> {:?}", line, entry));
+            renamed.insert(line.clone(), entry.clone());
+        }
+        if seen.insert(entry.clone()) {
+            entries.push(entry);
+        } else {
+            changes.push(format!("remove duplicate {:?}", entry));
+        }
+    }
+
+    // The earliest timestamp of each entry, in the original order.
+    let times = read_lines(&times_path(path))?;
+    let mut times_seen: HashSet<String> = HashSet::new();
+    let mut new_times: Vec<String> = Vec::new();
+    for line in &times {
+        let Some((time, entry)) = line.split_once('\t') else {
+            new_times.push(line.clone());
+            continue;
+        };
+        let entry = renamed
+            .get(entry)
+            .cloned()
+            .map_or_else(|| normalize(entry), Ok)?;
+        if times_seen.insert(entry.clone()) {
+            new_times.push(format!("{}\t{}", time, entry));
+        }
+    }
+
+    for change in &changes {
+        println!("{}: {}", path.display(), change);
+    }
+    if changes.is_empty() {
+        println!("{}: up to date", path.display());
+        return Ok(false);
+    }
+    if dry_run {
+        return Ok(true);
+    }
+
+    let mut out = header.lines();
+    out.extend(entries);
+    replace(path, &out)?;
+    if !times.is_empty() && new_times != times {
+        replace(&times_path(path), &new_times)?;
+    }
+    info!("Migrated {:?}, the old file is kept as .bak", path);
+    Ok(true)
+}
+
+// Upgrade the --delete and --keep label files to the current format: a version header,
+// absolute normalized paths and no duplicate entries.
+pub fn run(args: &MigrateArgs) -> io::Result<()> {
+    let common = &args.common;
+    let mut changed = 0;
+    for path in [&common.delete, &common.keep] {
+        if migrate(path, common, args.dry_run)? {
+            changed += 1;
+        }
+    }
+    if args.dry_run && changed > 0 {
+        println!("Dry run, {} label files would change", changed);
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use crate::walk::FileMeta;
+use crate::Label;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirSize {
+    /// The number of files in the directory.
+    Files,
+    /// The total bytes of the files in the directory.
+    Bytes,
+    /// Both, as separate features.
+    Both,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgeFrom {
+    /// The birth time, falling back to the modification time where it is not recorded.
+    Created,
+    Modified,
+    Accessed,
+}
+
+// The log file size, the age in days and the log size of the directory as chosen by DirSize.
+type Values = Vec<f64>;
+
+// Binned likelihoods of numeric file features, learned from the labels in place of the hand
+// tuned --file-size-log-base. Bin edges are quantiles over all files so each bin holds a similar
+// number of them.
+#[derive(Debug, Clone)]
+pub struct NumericModel {
+    values: HashMap<PathBuf, Values>,
+    // The upper edges of all but the last bin, per feature.
+    edges: Vec<Vec<f64>>,
+    // (delete, keep) counts per feature and bin.
+    counts: Vec<Vec<(usize, usize)>>,
+    delete: usize,
+    keep: usize,
+}
+
+impl NumericModel {
+    pub fn new(
+        files: &HashMap<PathBuf, FileMeta>,
+        bins: usize,
+        dir_size: DirSize,
+        age_from: AgeFrom,
+    ) -> Self {
+        let bins = bins.max(1);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        // (files, bytes) per directory.
+        let mut dirs: HashMap<&Path, (u64, u64)> = HashMap::new();
+        for (path, meta) in files {
+            if let Some(dir) = path.parent() {
+                let e = dirs.entry(dir).or_default();
+                e.0 += 1;
+                e.1 += meta.size;
+            }
+        }
+
+        let values: HashMap<PathBuf, Values> = files
+            .iter()
+            .map(|(path, meta)| {
+                let (dir_files, dir_bytes) = path
+                    .parent()
+                    .and_then(|dir| dirs.get(dir))
+                    .cloned()
+                    .unwrap_or((1, meta.size));
+                let time = match age_from {
+                    AgeFrom::Created => meta.created,
+                    AgeFrom::Modified => meta.modified,
+                    AgeFrom::Accessed => meta.accessed,
+                };
+                let mut values = vec![
+                    ((meta.size + 1) as f64).ln(),
+                    now.saturating_sub(time) as f64 / 86400.0,
+                ];
+                if dir_size != DirSize::Bytes {
+                    values.push((dir_files as f64).ln());
+                }
+                if dir_size != DirSize::Files {
+                    values.push(((dir_bytes + 1) as f64).ln());
+                }
+                (path.clone(), values)
+            })
+            .collect();
+
+        let features = values.values().next().map_or(0, Vec::len);
+        let edges = (0..features)
+            .map(|f| {
+                let mut column: Vec<f64> = values.values().map(|v| v[f]).collect();
+                column.sort_by(|a, b| a.total_cmp(b));
+                let mut edges: Vec<f64> = (1..bins)
+                    .filter_map(|b| column.get(b * column.len() / bins).cloned())
+                    .collect();
+                edges.dedup();
+                edges
+            })
+            .collect::<Vec<Vec<f64>>>();
+        let counts = edges.iter().map(|e| vec![(0, 0); e.len() + 1]).collect();
+
+        Self {
+            values,
+            edges,
+            counts,
+            delete: 0,
+            keep: 0,
+        }
+    }
+
+    fn bins(&self, path: &Path) -> Option<impl Iterator<Item = (usize, usize)> + '_> {
+        let values = self.values.get(path)?;
+        Some(
+            self.edges
+                .iter()
+                .zip(values)
+                .enumerate()
+                .map(|(f, (edges, v))| (f, edges.partition_point(|e| e <= v))),
+        )
+    }
+
+    pub fn train(&mut self, label: Label, path: &Path) {
+        let Some(bins) = self.bins(path) else {
+            return;
+        };
+        let bins: Vec<(usize, usize)> = bins.collect();
+        for (f, b) in bins {
+            let counts = &mut self.counts[f][b];
+            match label {
+                Label::Delete => counts.0 += 1,
+                Label::Keep => counts.1 += 1,
+            }
+        }
+        match label {
+            Label::Delete => self.delete += 1,
+            Label::Keep => self.keep += 1,
+        }
+    }
+
+    // The summed Laplace smoothed delete log likelihood ratios of the bins of a file.
+    pub fn log_odds(&self, path: &Path) -> f64 {
+        let Some(bins) = self.bins(path) else {
+            return 0.0;
+        };
+        bins.map(|(f, b)| {
+            let n = self.counts[f].len() as f64;
+            let (delete, keep) = self.counts[f][b];
+            ((delete + 1) as f64 / (self.delete as f64 + n)).ln()
+                - ((keep + 1) as f64 / (self.keep as f64 + n)).ln()
+        })
+        .sum()
+    }
+}
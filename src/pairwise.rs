@@ -0,0 +1,227 @@
+use crate::vlc::VLCProcessHandle;
+use crate::{Args, FileState, Model, INTERRUPTED};
+use log::*;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+// Pairwise preferences, one "winner<TAB>loser" line per comparison.
+#[derive(Debug)]
+pub struct Comparisons {
+    path: PathBuf,
+    pairs: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Comparisons {
+    pub fn from(path: &Path) -> io::Result<Self> {
+        let mut pairs = Vec::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in io::BufReader::new(file).lines().map_while(Result::ok) {
+                    match line.split_once('\t') {
+                        Some((winner, loser)) => pairs.push((winner.into(), loser.into())),
+                        None => warn!("Ignoring malformed comparison {:?}", line),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            pairs,
+        })
+    }
+
+    fn update(&mut self, winner: &Path, loser: &Path) -> io::Result<()> {
+        self.pairs.push((winner.to_owned(), loser.to_owned()));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}\t{}", winner.display(), loser.display())?;
+        Ok(())
+    }
+
+    fn compared(&self, a: &Path, b: &Path) -> bool {
+        self.pairs
+            .iter()
+            .any(|(w, l)| (w == a && l == b) || (w == b && l == a))
+    }
+
+    // Bradley-Terry log strengths fitted with the MM algorithm. Every file also plays one win
+    // and one loss against a virtual opponent of strength 1, which keeps unbeaten and winless
+    // files finite.
+    pub fn strengths(&self) -> HashMap<PathBuf, f64> {
+        let mut wins: HashMap<&Path, f64> = HashMap::new();
+        let mut games: HashMap<&Path, Vec<&Path>> = HashMap::new();
+        for (winner, loser) in &self.pairs {
+            *wins.entry(winner).or_default() += 1.0;
+            wins.entry(loser).or_default();
+            games.entry(winner).or_default().push(loser);
+            games.entry(loser).or_default().push(winner);
+        }
+
+        let mut strength: HashMap<&Path, f64> = wins.keys().map(|p| (*p, 1.0)).collect();
+        for _ in 0..100 {
+            let next: HashMap<&Path, f64> = strength
+                .iter()
+                .map(|(path, p)| {
+                    let denom: f64 = games[path].iter().map(|o| 1.0 / (p + strength[o])).sum();
+                    (*path, (wins[path] + 1.0) / (denom + 2.0 / (p + 1.0)))
+                })
+                .collect();
+            strength = next;
+        }
+
+        strength
+            .into_iter()
+            .map(|(path, p)| (path.to_owned(), p.ln()))
+            .collect()
+    }
+}
+
+// Play the two best ranked files that have not been compared yet, the file playing when VLC is
+// paused is preferred over the other one. Stopping VLC skips the pair.
+pub fn run(args: &Args) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+    let tokenizer = &model.tokenizer;
+    let classifier = &model.classifier;
+    let mut comparisons = Comparisons::from(&args.comparisons)?;
+
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+        .expect("Failed to set Ctrl-C handler");
+
+    let norm_log_odds = classifier.norm_log_odds();
+    let mut files: Vec<FileState> = model
+        .files
+        .into_par_iter()
+        .map(|(path, meta)| {
+            let ngrams = tokenizer.ngrams_cached(&path);
+            let mut file = FileState::new(
+                path,
+                ngrams,
+                meta.size,
+                args.file_size_log_base,
+                common.seed,
+            );
+            file.init(classifier, tokenizer);
+            file.ngrams = None;
+            file
+        })
+        .collect();
+
+    let mut vlc = VLCProcessHandle::new(&args.vlc, &common.path_map);
+    let mut skipped: HashSet<(usize, usize)> = HashSet::new();
+    let mut compared = 0;
+
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        if args.max_labels.is_some_and(|max| compared >= max) {
+            info!("Reached --max-labels");
+            break;
+        }
+
+        let strengths = comparisons.strengths();
+        for file in files.iter_mut() {
+            let strength = strengths.get(&file.path).cloned().unwrap_or_default();
+            file.pairwise_score = args.pairwise_weight * strength;
+            file.update(norm_log_odds);
+        }
+
+        // Best first.
+        let mut order: Vec<usize> = (0..files.len()).collect();
+        order.sort_by(|a, b| {
+            let (a, b) = (&files[*a], &files[*b]);
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.tie_break.cmp(&a.tie_break))
+                .then_with(|| b.path.cmp(&a.path))
+        });
+
+        let pair = order.iter().enumerate().find_map(|(n, a)| {
+            order[n + 1..]
+                .iter()
+                .find(|b| {
+                    !skipped.contains(&(*a, **b))
+                        && !comparisons.compared(&files[*a].path, &files[**b].path)
+                })
+                .map(|b| (*a, *b))
+        });
+        let Some((a, b)) = pair else {
+            info!("Every pair has been compared");
+            break;
+        };
+        let (path_a, path_b) = (&files[a].path, &files[b].path);
+        println!();
+        println!("A: {:?}", path_a);
+        println!("B: {:?}", path_b);
+
+        let osd = if args.osd {
+            vec![
+                "A: PAUSE=prefer STOP=skip".to_string(),
+                "B: PAUSE=prefer STOP=skip".to_string(),
+            ]
+        } else {
+            Vec::new()
+        };
+        if let Err(e) = vlc
+            .play(&[path_a, path_b], &osd)
+            .and_then(|_| vlc.wait_for_status())
+        {
+            error!("Vlc startup error {:?}", e);
+            skipped.insert((a, b));
+            continue;
+        }
+
+        let winner = loop {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                break None;
+            }
+
+            let status = match vlc.status() {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("Status error: {:?}", e);
+                    break None;
+                }
+            };
+
+            match status.state() {
+                "stopped" => break None,
+                "paused" => {
+                    let file_name = status.file_name();
+                    let is = |path: &Path| {
+                        path.file_name().map(|n| n.to_string_lossy().to_string()) == file_name
+                    };
+                    if is(path_a) {
+                        break Some((a, b));
+                    } else if is(path_b) {
+                        break Some((b, a));
+                    }
+                    error!("Paused on an unexpected file {:?}", file_name);
+                    break None;
+                }
+                _ => {}
+            }
+        };
+
+        match winner {
+            Some((winner, loser)) => {
+                comparisons.update(&files[winner].path, &files[loser].path)?;
+                compared += 1;
+                info!("{:?} > {:?}", files[winner].path, files[loser].path);
+            }
+            None => {
+                skipped.insert((a, b));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+use std::str::FromStr;
+
+// A path prefix substitution like `/mnt/nas=\\NAS\media`, for playlists and players that see
+// the library under another path, for example on another machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMap {
+    from: String,
+    to: String,
+}
+
+impl FromStr for PathMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected FROM=TO, got {:?}", s))?;
+        if from.is_empty() {
+            return Err(format!("empty prefix in {:?}", s));
+        }
+        Ok(Self {
+            from: from.trim_end_matches(['/', '\\']).to_string(),
+            to: to.trim_end_matches(['/', '\\']).to_string(),
+        })
+    }
+}
+
+// A drive letter or UNC prefix.
+fn is_windows(path: &str) -> bool {
+    path.starts_with(r"\\") || path.as_bytes().get(1) == Some(&b':')
+}
+
+// The path as the first map with a matching prefix rewrites it, whole path components only, or
+// the path unchanged. The separators after the prefix follow the style of the new prefix.
+pub fn map(maps: &[PathMap], path: &Path) -> Option<String> {
+    let s = path.to_string_lossy();
+    maps.iter().find_map(|map| {
+        let rest = s.strip_prefix(map.from.as_str())?;
+        if !(rest.is_empty() || rest.starts_with(['/', '\\'])) {
+            return None;
+        }
+        let rest = if is_windows(&map.to) {
+            rest.replace('/', "\\")
+        } else if is_windows(&map.from) {
+            rest.replace('\\', "/")
+        } else {
+            rest.to_string()
+        };
+        Some(format!("{}{}", map.to, rest))
+    })
+}
+
+// The path to write to a playlist or hand to a player.
+pub fn display(maps: &[PathMap], path: &Path) -> String {
+    map(maps, path).unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maps(maps: &[&str]) -> Vec<PathMap> {
+        maps.iter().map(|m| m.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn to_unc() {
+        let maps = maps(&[r"/mnt/nas/=\\NAS\media"]);
+        assert_eq!(
+            display(&maps, Path::new("/mnt/nas/films/a.mkv")),
+            r"\\NAS\media\films\a.mkv"
+        );
+    }
+
+    #[test]
+    fn from_drive() {
+        let maps = maps(&[r"D:\Videos=/srv/videos"]);
+        assert_eq!(
+            display(&maps, Path::new(r"D:\Videos\films\a.mkv")),
+            "/srv/videos/films/a.mkv"
+        );
+    }
+
+    #[test]
+    fn whole_components() {
+        let maps = maps(&["/mnt/nas=/media", "/mnt=/m"]);
+        assert_eq!(
+            display(&maps, Path::new("/mnt/nas2/a.mkv")),
+            "/m/nas2/a.mkv"
+        );
+        assert_eq!(display(&maps, Path::new("/mnt/nas/a.mkv")), "/media/a.mkv");
+        assert_eq!(display(&maps, Path::new("/srv/a.mkv")), "/srv/a.mkv");
+    }
+
+    #[test]
+    fn rejected() {
+        assert!("/mnt/nas".parse::<PathMap>().is_err());
+        assert!("=/media".parse::<PathMap>().is_err());
+    }
+}
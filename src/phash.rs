@@ -0,0 +1,131 @@
+use crate::cache::Cache;
+use crate::dupes::{probe_duration, probe_pool};
+use crate::tokenizer::Tokenizer;
+use crate::walk::FileMeta;
+use crate::CommonArgs;
+use log::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Frames sampled per video, and the side of the grayscale frames the DCT runs over.
+const FRAMES: usize = 8;
+const SIZE: usize = 32;
+
+// A 64 bit pHash of a SIZE x SIZE grayscale frame: the signs of the lowest 8x8 DCT frequencies
+// relative to their median.
+fn frame_hash(pixels: &[u8]) -> u64 {
+    let mut coeffs = [0.0; 64];
+    for u in 0..8 {
+        for v in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += pixels[y * SIZE + x] as f64
+                        * (PI * (2 * x + 1) as f64 * u as f64 / (2 * SIZE) as f64).cos()
+                        * (PI * (2 * y + 1) as f64 * v as f64 / (2 * SIZE) as f64).cos();
+                }
+            }
+            coeffs[v * 8 + u] = sum;
+        }
+    }
+    // The DC term is left out of the median as it only reflects overall brightness.
+    let mut sorted: Vec<f64> = coeffs[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    coeffs.iter().enumerate().fold(
+        0,
+        |hash, (i, c)| if *c > median { hash | 1 << i } else { hash },
+    )
+}
+
+// Hashes of FRAMES evenly spaced frames, decoded by ffmpeg.
+fn video_hash(path: &Path) -> io::Result<Vec<u64>> {
+    let duration = probe_duration(path)
+        .filter(|d| *d > 0)
+        .ok_or_else(|| io::Error::other(format!("No duration for {:?}", path)))?;
+    let interval = duration as f64 / FRAMES as f64;
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-ss"])
+        .arg(format!("{:.3}", interval / 2.0))
+        .arg("-i")
+        .arg(path)
+        .arg("-vf")
+        .arg(format!(
+            "fps=1/{:.3},scale={}:{},format=gray",
+            interval, SIZE, SIZE
+        ))
+        .args(["-frames:v", &FRAMES.to_string(), "-f", "rawvideo", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "ffmpeg failed for {:?}: {}",
+            path, output.status
+        )));
+    }
+    Ok(output
+        .stdout
+        .chunks_exact(SIZE * SIZE)
+        .map(frame_hash)
+        .collect())
+}
+
+// The fraction of matching bits between the frames at the same positions of two videos, around
+// 0.5 for unrelated videos and close to 1 for re-encodes.
+pub fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    let distance: u32 = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum();
+    1.0 - distance as f64 / (64 * n) as f64
+}
+
+// The hashes of the given files, computed with ffmpeg when they are not in the --cache file.
+// Files that fail to decode are left out.
+pub fn hashes(common: &CommonArgs, paths: &[PathBuf]) -> io::Result<HashMap<PathBuf, Vec<u64>>> {
+    let mut cache = match &common.cache {
+        Some(path) => Cache::load(path, &Tokenizer::cache_key(common)),
+        None => Cache::default(),
+    };
+
+    let metas: Vec<(&PathBuf, FileMeta)> = paths
+        .iter()
+        .filter_map(|path| Some((path, FileMeta::new(&std::fs::metadata(path).ok()?))))
+        .collect();
+    let mut ret = HashMap::new();
+    let mut missing = Vec::new();
+    for (path, meta) in &metas {
+        match cache.phash(path, meta) {
+            Some(hash) => {
+                ret.insert((*path).clone(), hash.to_vec());
+            }
+            None => missing.push((*path, *meta)),
+        }
+    }
+    info!("Hashing {} of {} files", missing.len(), metas.len());
+
+    let computed: Vec<(&PathBuf, FileMeta, Vec<u64>)> = probe_pool(common)?.install(|| {
+        missing
+            .into_par_iter()
+            .filter_map(|(path, meta)| match video_hash(path) {
+                Ok(hash) => Some((path, meta, hash)),
+                Err(e) => {
+                    warn!("pHash {:?}: {}", path, e);
+                    None
+                }
+            })
+            .collect()
+    });
+    for (path, meta, hash) in computed {
+        cache.insert_phash(path, &meta, hash.clone());
+        ret.insert(path.clone(), hash);
+    }
+    cache.save()?;
+    Ok(ret)
+}
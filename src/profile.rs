@@ -0,0 +1,78 @@
+use log::*;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+struct Phase {
+    name: &'static str,
+    // How many times the phase ran, for example once per retrain.
+    count: usize,
+    seconds: f64,
+    // The peak resident memory of the process when the phase last ended.
+    max_rss_kib: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    version: &'static str,
+    phases: &'a [Phase],
+    max_rss_kib: Option<u64>,
+}
+
+// The phases timed so far, in the order they first ran.
+static PHASES: Mutex<Vec<Phase>> = Mutex::new(Vec::new());
+
+// Time an expression as a named phase of the --profile-out report.
+macro_rules! time_it {
+    ($name:expr, $e:expr) => {{
+        let start = std::time::Instant::now();
+        let ret = $e;
+        crate::profile::record($name, start.elapsed());
+        ret
+    }};
+}
+pub(crate) use time_it;
+
+pub fn record(name: &'static str, elapsed: Duration) {
+    debug!("{} took {:?}", name, elapsed);
+    let max_rss_kib = max_rss_kib();
+    let mut phases = PHASES.lock().unwrap();
+    match phases.iter_mut().find(|phase| phase.name == name) {
+        Some(phase) => {
+            phase.count += 1;
+            phase.seconds += elapsed.as_secs_f64();
+            phase.max_rss_kib = max_rss_kib;
+        }
+        None => phases.push(Phase {
+            name,
+            count: 1,
+            seconds: elapsed.as_secs_f64(),
+            max_rss_kib,
+        }),
+    }
+}
+
+// The memory high-water mark, where the platform reports it.
+fn max_rss_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+// Write the phase timings as JSON.
+pub fn write(path: &Path) -> io::Result<()> {
+    let phases = PHASES.lock().unwrap();
+    let report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        phases: &phases,
+        max_rss_kib: max_rss_kib(),
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, &report)?;
+    writeln!(out)?;
+    out.flush()
+}
@@ -0,0 +1,13 @@
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+
+// A progress bar on stderr for a phase over a known number of items. It is drawn only when
+// stderr is a terminal and cleared when dropped, so logs and piped output are unchanged.
+pub fn bar(len: usize, message: &'static str) -> ProgressBar {
+    let style = ProgressStyle::with_template("{msg:>16} [{bar:40}] {pos}/{len} {eta}")
+        .unwrap()
+        .progress_chars("=> ");
+    ProgressBar::new(len as u64)
+        .with_style(style)
+        .with_message(message)
+        .with_finish(ProgressFinish::AndClear)
+}
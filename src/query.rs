@@ -0,0 +1,405 @@
+use regex::Regex;
+use std::path::Path;
+use std::str::FromStr;
+
+// The fields a query is evaluated over.
+pub struct Fields<'a> {
+    pub path: &'a Path,
+    pub size: u64,
+    pub score: f64,
+    pub classifier_score: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Size,
+    Score,
+    Classifier,
+    Path,
+    Name,
+    Ext,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    String(String),
+    // A glob compiled for ~ and !~.
+    Glob(Regex),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Field, Op, Value),
+}
+
+// A filter over files like `size > 1GB && score > 0.8 && path ~ "*/2023/*"`.
+//
+// Comparisons are FIELD OP VALUE, combined with &&, ||, ! and parentheses. The numeric fields
+// are size (bytes, with an optional KB, MB, GB, TB or KiB, MiB, GiB, TiB suffix), score and
+// classifier. The string fields are path, name and ext, compared with == and != or matched
+// against a glob with ~ and !~, where * matches any characters including / and ? one character.
+#[derive(Debug, Clone)]
+pub struct Query(Expr);
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = lex(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Ok(Self(expr)),
+        }
+    }
+}
+
+impl Query {
+    pub fn matches(&self, fields: &Fields) -> bool {
+        eval(&self.0, fields)
+    }
+}
+
+fn eval(expr: &Expr, fields: &Fields) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, fields) && eval(b, fields),
+        Expr::Or(a, b) => eval(a, fields) || eval(b, fields),
+        Expr::Not(a) => !eval(a, fields),
+        Expr::Compare(field, op, value) => {
+            let number = match field {
+                Field::Size => Some(fields.size as f64),
+                Field::Score => Some(fields.score),
+                Field::Classifier => Some(fields.classifier_score),
+                _ => None,
+            };
+            if let (Some(lhs), Value::Number(rhs)) = (number, value) {
+                return match op {
+                    Op::Eq => lhs == *rhs,
+                    Op::Ne => lhs != *rhs,
+                    Op::Lt => lhs < *rhs,
+                    Op::Le => lhs <= *rhs,
+                    Op::Gt => lhs > *rhs,
+                    Op::Ge => lhs >= *rhs,
+                    Op::Match | Op::NotMatch => false,
+                };
+            }
+            let lhs = match field {
+                Field::Path => fields.path.to_string_lossy(),
+                Field::Name => fields
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                Field::Ext => fields
+                    .path
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                _ => return false,
+            };
+            match (op, value) {
+                (Op::Eq, Value::String(rhs)) => lhs == rhs.as_str(),
+                (Op::Ne, Value::String(rhs)) => lhs != rhs.as_str(),
+                (Op::Match, Value::Glob(re)) => re.is_match(&lhs),
+                (Op::NotMatch, Value::Glob(re)) => !re.is_match(&lhs),
+                _ => false,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    String(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn lex(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('!', Some('~')) => (Token::Op(Op::NotMatch), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('~', _) => (Token::Op(Op::Match), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('"', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or("unterminated string")?;
+                let string: String = chars[i + 1..i + 1 + end].iter().collect();
+                (Token::String(string), end + 2)
+            }
+            (c, _) if c.is_ascii_digit() || c == '.' || c == '-' => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_ascii_alphanumeric() || *c == '.' || *c == '-'))
+                    .unwrap_or(chars.len() - i);
+                let word: String = chars[i..i + len].iter().collect();
+                (Token::Number(number(&word)?), len)
+            }
+            (c, _) if c.is_ascii_alphabetic() => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+                    .unwrap_or(chars.len() - i);
+                (Token::Ident(chars[i..i + len].iter().collect()), len)
+            }
+            (c, _) => return Err(format!("unexpected {:?}", c)),
+        };
+        ret.push(token);
+        i += len;
+    }
+    Ok(ret)
+}
+
+// A number with an optional size suffix.
+fn number(word: &str) -> Result<f64, String> {
+    let split = word
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(word.len());
+    let (value, unit) = word.split_at(split);
+    let multiplier = match unit.to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "kb" => 1e3,
+        "mb" => 1e6,
+        "gb" => 1e9,
+        "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown unit in {:?}", word)),
+    };
+    let value: f64 = value.parse().map_err(|e| format!("{:?}: {}", word, e))?;
+    Ok(value * multiplier)
+}
+
+fn glob(pattern: &str) -> Result<Regex, String> {
+    let mut re = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).map_err(|e| e.to_string())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Open) {
+            let expr = self.or()?;
+            if !self.eat(&Token::Close) {
+                return Err("expected )".to_string());
+            }
+            return Ok(expr);
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let field = match self.next() {
+            Some(Token::Ident(name)) => match name.as_str() {
+                "size" => Field::Size,
+                "score" => Field::Score,
+                "classifier" => Field::Classifier,
+                "path" => Field::Path,
+                "name" => Field::Name,
+                "ext" => Field::Ext,
+                _ => return Err(format!("unknown field {:?}", name)),
+            },
+            token => return Err(format!("expected a field, got {:?}", token)),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            token => return Err(format!("expected an operator, got {:?}", token)),
+        };
+        let numeric = matches!(field, Field::Size | Field::Score | Field::Classifier);
+        let value = match (self.next(), numeric, op) {
+            (Some(Token::Number(n)), true, Op::Match | Op::NotMatch) => {
+                return Err(format!("~ needs a string, got {}", n))
+            }
+            (Some(Token::Number(n)), true, _) => Value::Number(n),
+            (Some(Token::String(s)), false, Op::Match | Op::NotMatch) => Value::Glob(glob(&s)?),
+            (Some(Token::String(s)), false, Op::Eq | Op::Ne) => Value::String(s),
+            (Some(Token::String(_)), false, op) => {
+                return Err(format!("{:?} compares numbers", op))
+            }
+            (token, _, _) => return Err(format!("unexpected {:?} after {:?}", token, field)),
+        };
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(query: &str, path: &str, size: u64, score: f64) -> bool {
+        let query: Query = query.parse().unwrap();
+        query.matches(&Fields {
+            path: Path::new(path),
+            size,
+            score,
+            classifier_score: score,
+        })
+    }
+
+    #[test]
+    fn precedence() {
+        // && binds tighter than ||.
+        assert!(matches(
+            "size > 1 || size > 5 && score > 1",
+            "a.mkv",
+            3,
+            0.0
+        ));
+        assert!(!matches(
+            "(size > 1 || size > 5) && score > 1",
+            "a.mkv",
+            3,
+            0.0
+        ));
+        assert!(matches(
+            "score > 1 && size > 5 || size > 1",
+            "a.mkv",
+            3,
+            0.0
+        ));
+    }
+
+    #[test]
+    fn not_and_parentheses() {
+        assert!(matches("!(size > 5)", "a.mkv", 3, 0.0));
+        assert!(!matches("!size < 5", "a.mkv", 3, 0.0));
+        assert!(matches(
+            "!(size > 5 || score > 1) && ext == \"mkv\"",
+            "a.mkv",
+            3,
+            0.0
+        ));
+        assert!(matches("!!(size == 3)", "a.mkv", 3, 0.0));
+    }
+
+    #[test]
+    fn units() {
+        assert!(matches("size == 1GB", "a.mkv", 1_000_000_000, 0.0));
+        assert!(matches("size == 1GiB", "a.mkv", 1 << 30, 0.0));
+        assert!(matches("size == 1.5kib", "a.mkv", 1536, 0.0));
+        assert!(matches(
+            "size > 1GB && size < 1GiB",
+            "a.mkv",
+            1_050_000_000,
+            0.0
+        ));
+        assert!("size > 1XB".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn globs() {
+        assert!(matches("path ~ \"*/2023/*\"", "/films/2023/a.mkv", 0, 0.0));
+        assert!(!matches("path ~ \"*/2023/*\"", "/films/2022/a.mkv", 0, 0.0));
+        assert!(matches("path !~ \"*/2023/*\"", "/films/2022/a.mkv", 0, 0.0));
+        assert!(matches("name ~ \"a.???\"", "/films/a.mkv", 0, 0.0));
+        // Regex characters in the pattern are literal.
+        assert!(!matches("name ~ \"a.m+v\"", "/films/a.mmkv", 0, 0.0));
+    }
+
+    #[test]
+    fn rejected() {
+        for query in [
+            "size ~ \"x\"",
+            "path > 1",
+            "path > \"x\"",
+            "name == \"unterminated",
+            "size >",
+            "bogus == 1",
+            "(size > 1",
+            "size > 1)",
+        ] {
+            assert!(query.parse::<Query>().is_err(), "{}", query);
+        }
+    }
+}
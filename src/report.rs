@@ -0,0 +1,215 @@
+use crate::tokenizer::{Ngram, Token};
+use crate::{round, CommonArgs, Model};
+use clap::Subcommand;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    command: ReportCommand,
+}
+
+impl ReportArgs {
+    pub fn common(&self) -> &CommonArgs {
+        match &self.command {
+            ReportCommand::NgramWeights(args) => &args.common,
+            ReportCommand::Vocab(args) => &args.common,
+            ReportCommand::Ngram(args) => &args.common,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ReportCommand {
+    /// List the ngrams with the strongest delete and keep log odds.
+    NgramWeights(NgramWeightsArgs),
+    /// List the token vocabulary by the number of files each token appears in, to see how
+    /// paths tokenize.
+    Vocab(VocabArgs),
+    /// Tokenize a text like a path and list its ngrams with their training counts and log odds.
+    Ngram(NgramArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct NgramWeightsArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// The number of ngrams to list for each label.
+    #[clap(long, default_value = "20")]
+    top: usize,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct VocabArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Print the vocabulary as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct NgramArgs {
+    /// The text to look up, for example a file name or a phrase from one.
+    text: String,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+pub fn run(args: &ReportArgs) -> io::Result<()> {
+    match &args.command {
+        ReportCommand::NgramWeights(args) => ngram_weights(args),
+        ReportCommand::Vocab(args) => vocab(args),
+        ReportCommand::Ngram(args) => ngram(args),
+    }
+}
+
+fn ngram_weights(args: &NgramWeightsArgs) -> io::Result<()> {
+    let model = Model::load(&args.common)?;
+    let tokenizer = &model.tokenizer;
+    let classifier = &model.classifier;
+
+    // Only ngrams seen in training, the rest share the prior.
+    let mut weights: Vec<(f64, Ngram)> = tokenizer
+        .ngram_tokens
+        .keys()
+        .filter(|ngram| classifier.counts(ngram) != (0.0, 0.0))
+        .map(|ngram| (classifier.log_odds(ngram), *ngram))
+        .collect();
+    weights.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let print = |(log_odds, ngram): &(f64, Ngram)| {
+        let (delete, keep) = classifier.counts(ngram);
+        let s = tokenizer.ngram_string(ngram).unwrap_or_default();
+        println!(
+            "{:>9.3} {:>7} {:>7}  {:?}",
+            log_odds,
+            round(delete),
+            round(keep),
+            s
+        );
+    };
+
+    println!("Delete ngrams");
+    println!("{:>9} {:>7} {:>7}  ngram", "log_odds", "delete", "keep");
+    for w in weights.iter().filter(|w| w.0 > 0.0).take(args.top) {
+        print(w);
+    }
+
+    println!();
+    println!("Keep ngrams");
+    println!("{:>9} {:>7} {:>7}  ngram", "log_odds", "delete", "keep");
+    for w in weights.iter().rev().filter(|w| w.0 < 0.0).take(args.top) {
+        print(w);
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct VocabEntry<'a> {
+    token: Token,
+    string: &'a str,
+    files: usize,
+    // "text" for tokens of the path text, "special" for structured, feature regex and media
+    // tokens and "chars" for --char-fallback character ngrams.
+    kind: &'static str,
+}
+
+fn vocab(args: &VocabArgs) -> io::Result<()> {
+    let model = Model::load(&args.common)?;
+    let tokenizer = &model.tokenizer;
+
+    // The number of library files, labeled or not, each token appears in.
+    let mut files: HashMap<Token, usize> = HashMap::new();
+    let paths = model
+        .files
+        .keys()
+        .cloned()
+        .chain(model.delete.iter())
+        .chain(model.keep.iter());
+    for path in paths {
+        let tokens: BTreeSet<Token> = tokenizer.tokenize_cached(&path).into_iter().collect();
+        for token in tokens {
+            *files.entry(token).or_default() += 1;
+        }
+    }
+
+    let mut entries: Vec<VocabEntry> = tokenizer
+        .token_string
+        .iter()
+        .map(|(token, string)| VocabEntry {
+            token: *token,
+            string,
+            files: files.get(token).cloned().unwrap_or_default(),
+            kind: if string.starts_with("<chars:") {
+                "chars"
+            } else if string.len() > 1 && string.starts_with('<') {
+                "special"
+            } else {
+                "text"
+            },
+        })
+        .collect();
+    entries.sort_by(|a, b| b.files.cmp(&a.files).then(a.token.cmp(&b.token)));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    println!("{:>7} {:>7} {:>7}  string", "token", "files", "kind");
+    for entry in &entries {
+        println!(
+            "{:>7} {:>7} {:>7}  {:?}",
+            entry.token.0, entry.files, entry.kind, entry.string
+        );
+    }
+    Ok(())
+}
+
+fn ngram(args: &NgramArgs) -> io::Result<()> {
+    let model = Model::load(&args.common)?;
+    let tokenizer = &model.tokenizer;
+    let classifier = &model.classifier;
+
+    let tokens = tokenizer.tokenize_cached(Path::new(&args.text));
+    println!("Tokens: {:?}", tokenizer.tokens_string(&tokens));
+
+    let ngrams = tokenizer.text_ngrams(&args.text);
+    let mut seen: HashSet<&str> = HashSet::new();
+    println!("{:>9} {:>7} {:>7}  ngram", "log_odds", "delete", "keep");
+    for (s, ngram) in &ngrams {
+        if !seen.insert(s) {
+            continue;
+        }
+        if *ngram == Ngram::default() {
+            println!(
+                "{:>9} {:>7} {:>7}  {:?} (not in the vocabulary)",
+                "-", "-", "-", s
+            );
+            continue;
+        }
+        let (delete, keep) = classifier.counts(ngram);
+        println!(
+            "{:>9.3} {:>7} {:>7}  {:?}",
+            classifier.log_odds(ngram),
+            round(delete),
+            round(keep),
+            s
+        );
+    }
+
+    let ids: Vec<Ngram> = ngrams.iter().map(|(_, ngram)| *ngram).collect();
+    println!();
+    println!(
+        "Delete log odds of the text: {:.3}",
+        classifier.predict_delete(&ids)
+    );
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use crate::media::MediaInfo;
+use crate::profile::time_it;
+use crate::progress;
+use crate::query::Query;
+use crate::{CommonArgs, FileState, Label, Model, State};
+use indicatif::ParallelProgressIterator;
+use log::*;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScoreArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The log base for the file size mixed into the score, as in interactive mode.
+    #[clap(long)]
+    file_size_log_base: Option<f64>,
+
+    /// Render the score distributions to an SVG or PNG file, chosen by extension.
+    #[clap(long)]
+    plot: Option<PathBuf>,
+
+    /// Print the scores as JSON, with the media server metadata of each file.
+    #[clap(long)]
+    json: bool,
+
+    /// Only print files matching a query like 'size > 1GB && score > 0.8 && path ~ "*/2023/*"',
+    /// see build --help.
+    #[clap(long = "where")]
+    query: Option<Query>,
+
+    /// Score the newline delimited paths read from stdin as they arrive, instead of the
+    /// unlabeled library files, for use as a filter in shell pipelines. Without library paths
+    /// the vocabulary is built from the labeled files.
+    #[clap(long, conflicts_with = "plot")]
+    pub stdin: bool,
+
+    /// Keep running, train on the labels appended to the label files, for example by a build
+    /// session, and print the best files again after each change.
+    #[clap(long, conflicts_with_all = ["stdin", "plot", "json"])]
+    watch: bool,
+
+    /// How often --watch checks the label files, in seconds.
+    #[clap(long, default_value = "2", requires = "watch")]
+    interval: f64,
+
+    /// How many of the best files to print, all of them by default or 20 with --watch.
+    #[clap(long)]
+    top: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    path: &'a Path,
+    score: f64,
+    classifier_score: f64,
+    file_size_score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    media: Option<&'a MediaInfo>,
+}
+
+fn score(args: &ScoreArgs, model: &Model, path: PathBuf, size: u64) -> FileState {
+    let tokenizer = &model.tokenizer;
+    let ngrams = tokenizer.ngrams_cached(&path);
+    let mut file = FileState::new(
+        path,
+        ngrams,
+        size,
+        args.file_size_log_base,
+        args.common.seed,
+    );
+    file.init(&model.classifier, tokenizer);
+    file.update(model.classifier.norm_log_odds());
+    file.ngrams = None;
+    file
+}
+
+// The unlabeled files matching --where, best delete candidates first.
+fn rank(args: &ScoreArgs, model: &Model) -> Vec<FileState> {
+    let bar = progress::bar(model.files.len(), "Scoring");
+    let mut files: Vec<FileState> = time_it!(
+        "score",
+        model
+            .files
+            .par_iter()
+            .progress_with(bar)
+            .map(|(path, meta)| score(args, model, path.clone(), meta.size))
+            .collect()
+    );
+
+    if let Some(query) = &args.query {
+        files.retain(|file| query.matches(&file.fields()));
+    }
+
+    files.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.tie_break.cmp(&a.tie_break))
+            .then_with(|| b.path.cmp(&a.path))
+    });
+    files
+}
+
+// The entries of the delete and keep files, as the model was trained on them.
+fn labels(common: &CommonArgs) -> io::Result<HashSet<(Label, String)>> {
+    let mut ret = HashSet::new();
+    for (label, path) in [(Label::Delete, &common.delete), (Label::Keep, &common.keep)] {
+        let mut state = State::from(path)?;
+        if common.canonicalize {
+            state.canonicalize();
+        }
+        ret.extend(state.contents.into_iter().map(|entry| (label, entry)));
+    }
+    Ok(ret)
+}
+
+// The modification times and sizes of the label files, to notice changes without reading them.
+fn stamps(common: &CommonArgs) -> Vec<Option<(SystemTime, u64)>> {
+    [&common.delete, &common.keep]
+        .iter()
+        .map(|path| {
+            let meta = std::fs::metadata(path).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        })
+        .collect()
+}
+
+// Print the best files after each change to the label files until interrupted. Appended labels
+// are trained on incrementally, removed ones reload the model since they cannot be untrained.
+fn watch(args: &ScoreArgs, mut model: Model) -> io::Result<()> {
+    let common = &args.common;
+    let top = args.top.unwrap_or(20);
+    let mut seen = labels(common)?;
+    let mut stamp = stamps(common);
+    loop {
+        let files = rank(args, &model);
+        println!("\n{} labels, {} candidates", seen.len(), files.len());
+        for file in files.iter().take(top) {
+            println!("{:>9.3}  {:?}", file.score, file.path);
+        }
+
+        loop {
+            std::thread::sleep(Duration::from_secs_f64(args.interval));
+            let now = stamps(common);
+            if now == stamp {
+                continue;
+            }
+            stamp = now;
+            let current = labels(common)?;
+            if current == seen {
+                continue;
+            }
+            if seen.is_subset(&current) {
+                for (label, entry) in current.difference(&seen) {
+                    info!("New label {} {:?}", label.as_str(), entry);
+                    model.train(*label, Path::new(entry));
+                }
+            } else {
+                info!("Labels were removed, reloading");
+                model = Model::load(common)?;
+            }
+            seen = current;
+            break;
+        }
+    }
+}
+
+// Score the unlabeled files with the model trained on the labeled files, best delete candidates
+// first.
+pub fn run(args: &ScoreArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+    if args.watch {
+        return watch(args, model);
+    }
+    let tokenizer = &model.tokenizer;
+
+    if args.stdin {
+        let mut out = io::stdout().lock();
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let path = PathBuf::from(line);
+            let size = std::fs::metadata(&path)
+                .map(|m| m.len())
+                .unwrap_or_default();
+            let file = score(args, &model, path, size);
+            if args
+                .query
+                .as_ref()
+                .is_some_and(|q| !q.matches(&file.fields()))
+            {
+                continue;
+            }
+            if args.json {
+                // One object per line.
+                let entry = Entry {
+                    path: &file.path,
+                    score: file.score,
+                    classifier_score: file.classifier_score,
+                    file_size_score: file.file_size_score,
+                    media: tokenizer.media.get(&file.path),
+                };
+                writeln!(out, "{}", serde_json::to_string(&entry)?)?;
+            } else {
+                writeln!(out, "{:>9.3}  {:?}", file.score, file.path)?;
+            }
+            out.flush()?;
+        }
+        return Ok(());
+    }
+
+    let mut files = rank(args, &model);
+    files.truncate(args.top.unwrap_or(files.len()));
+
+    if args.json {
+        let entries: Vec<Entry> = files
+            .iter()
+            .map(|file| Entry {
+                path: &file.path,
+                score: file.score,
+                classifier_score: file.classifier_score,
+                file_size_score: file.file_size_score,
+                media: tokenizer.media.get(&file.path),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for file in &files {
+            println!("{:>9.3}  {:?}", file.score, file.path);
+        }
+    }
+
+    if let Some(plot) = &args.plot {
+        let column = |f: fn(&FileState) -> f64| -> Vec<f64> { files.iter().map(f).collect() };
+        crate::viz::export(
+            plot,
+            &[
+                ("File size", column(|f| f.file_size_score)),
+                ("Classifier", column(|f| f.classifier_score)),
+                ("Total", column(|f| f.score)),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
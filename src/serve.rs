@@ -0,0 +1,160 @@
+use crate::{walk, CommonArgs, Label, Model};
+use log::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The address to listen on.
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Require "Authorization: Bearer TOKEN" on every request. Also read from the
+    /// CLASSI_CINE_API_TOKEN environment variable.
+    #[clap(long, env = "CLASSI_CINE_API_TOKEN", hide_env_values = true)]
+    api_token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Scored<'a> {
+    path: &'a Path,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct LabelRequest {
+    path: PathBuf,
+    label: String,
+}
+
+type Reply = (u16, serde_json::Value);
+
+// A path in the form the directory walk names files, so that it matches the candidates and
+// the label files.
+fn normalize(common: &CommonArgs, path: &Path) -> PathBuf {
+    // Rebuilt from its components to drop repeated separators and "." components.
+    let path: PathBuf = walk::normalize(path).components().collect();
+    match std::fs::canonicalize(&path) {
+        Ok(canonical) if common.canonicalize => walk::normalize(&canonical),
+        _ => path,
+    }
+}
+
+fn error(status: u16, message: &str) -> Reply {
+    (status, serde_json::json!({ "error": message }))
+}
+
+// Answer scoring requests from other tools over HTTP with the model trained on the labels:
+//
+//     GET /score?path=PATH     {"path": ..., "score": ...}
+//     GET /rank?limit=N        the N best delete candidates, [{"path": ..., "score": ...}]
+//     POST /label              {"path": ..., "label": "delete" | "keep"}, trains the model and
+//                              appends to the label file, 409 if the file is already labeled
+pub fn run(args: &ServeArgs) -> io::Result<()> {
+    let mut model = Model::load(&args.common)?;
+    let server = Server::http(&args.listen).map_err(|e| io::Error::other(e.to_string()))?;
+    info!("Listening on http://{}", args.listen);
+    if args.api_token.is_none() {
+        warn!("No --api-token, requests are not authenticated");
+    }
+
+    for mut request in server.incoming_requests() {
+        let (status, body) = handle(args, &mut model, &mut request);
+        debug!("{} {} {}", request.method(), request.url(), status);
+        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(header);
+        if let Err(e) = request.respond(response) {
+            warn!("Respond error {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(args: &ServeArgs, model: &mut Model, request: &mut Request) -> Reply {
+    if let Some(token) = &args.api_token {
+        let expected = format!("Bearer {}", token);
+        let authorized = request
+            .headers()
+            .iter()
+            .any(|h| h.field.equiv("Authorization") && h.value.as_str() == expected);
+        if !authorized {
+            return error(401, "unauthorized");
+        }
+    }
+
+    let url = request.url().to_string();
+    let (route, query) = url.split_once('?').unwrap_or((&url, ""));
+    let query: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+
+    match (request.method(), route) {
+        (Method::Get, "/score") => {
+            let Some(path) = query.get("path") else {
+                return error(400, "missing path");
+            };
+            let path = Path::new(path);
+            let score = model.score(path);
+            (200, serde_json::json!(Scored { path, score }))
+        }
+        (Method::Get, "/rank") => {
+            let limit = match query.get("limit").map(|l| l.parse::<usize>()) {
+                None => 20,
+                Some(Ok(limit)) => limit,
+                Some(Err(_)) => return error(400, "invalid limit"),
+            };
+            let paths: Vec<&Path> = model.candidates().collect();
+            let mut scored: Vec<Scored> = paths
+                .into_par_iter()
+                .map(|path| Scored {
+                    path,
+                    score: model.score(path),
+                })
+                .collect();
+            scored.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.path.cmp(b.path)));
+            scored.truncate(limit);
+            (200, serde_json::json!(scored))
+        }
+        (Method::Post, "/label") => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return error(400, &e.to_string());
+            }
+            let label: LabelRequest = match serde_json::from_str(&body) {
+                Ok(label) => label,
+                Err(e) => return error(400, &e.to_string()),
+            };
+            let label_kind = match label.label.as_str() {
+                "delete" => Label::Delete,
+                "keep" => Label::Keep,
+                _ => return error(400, "label must be delete or keep"),
+            };
+            let path = normalize(&args.common, &label.path);
+            // A retried request would train on the file twice, and a relabel would leave
+            // conflicting labels.
+            if let Some(existing) = model.label(&path) {
+                return error(409, &format!("already labeled {}", existing.as_str()));
+            }
+            if let Err(e) = model.record(label_kind, &path) {
+                return error(500, &e.to_string());
+            }
+            model.train(label_kind, &path);
+            info!("{:?} ({})", path, label_kind.as_str().to_uppercase());
+            (
+                200,
+                serde_json::json!({ "path": path, "label": label.label }),
+            )
+        }
+        (_, "/score" | "/rank" | "/label") => error(405, "method not allowed"),
+        _ => error(404, "not found"),
+    }
+}
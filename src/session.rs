@@ -0,0 +1,145 @@
+use crate::Label;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Statistics of one interactive session, printed when it ends and optionally appended to a
+// history file as one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(skip, default = "Instant::now")]
+    start: Instant,
+    // The duration of the earlier runs of a resumed session.
+    #[serde(skip)]
+    prior_secs: f64,
+
+    // Unix timestamp in seconds.
+    started_at: u64,
+    duration_secs: f64,
+    classified: usize,
+    delete: usize,
+    keep: usize,
+    secs_per_classification: f64,
+    // Distinct ngrams seen in training at the start and end of the session.
+    vocab_start: usize,
+    vocab_end: usize,
+    remaining: usize,
+    // Unlabeled files scoring above the threshold, an estimate of the deletes left to find.
+    remaining_above_threshold: usize,
+    threshold: f64,
+}
+
+impl Session {
+    pub fn new(vocab_size: usize, threshold: f64) -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            start: Instant::now(),
+            prior_secs: 0.0,
+            started_at,
+            duration_secs: 0.0,
+            classified: 0,
+            delete: 0,
+            keep: 0,
+            secs_per_classification: 0.0,
+            vocab_start: vocab_size,
+            vocab_end: vocab_size,
+            remaining: 0,
+            remaining_above_threshold: 0,
+            threshold,
+        }
+    }
+
+    pub fn label(&mut self, label: Label) {
+        self.classified += 1;
+        match label {
+            Label::Delete => self.delete += 1,
+            Label::Keep => self.keep += 1,
+        }
+    }
+
+    pub fn finish(&mut self, vocab_size: usize, remaining_scores: impl Iterator<Item = f64>) {
+        self.duration_secs = crate::round(self.prior_secs + self.start.elapsed().as_secs_f64());
+        if self.classified > 0 {
+            self.secs_per_classification =
+                crate::round(self.duration_secs / self.classified as f64);
+        }
+        self.vocab_end = vocab_size;
+        self.remaining = 0;
+        self.remaining_above_threshold = 0;
+        for score in remaining_scores {
+            self.remaining += 1;
+            if score > self.threshold {
+                self.remaining_above_threshold += 1;
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        println!();
+        println!("Session summary");
+        println!(
+            "Classified: {} ({} delete, {} keep)",
+            self.classified, self.delete, self.keep
+        );
+        println!(
+            "Time: {:.0}s, {:.1}s per classification",
+            self.duration_secs, self.secs_per_classification
+        );
+        println!(
+            "Vocabulary: {} -> {} ngrams",
+            self.vocab_start, self.vocab_end
+        );
+        println!(
+            "Remaining: {} files, {} scoring above {}",
+            self.remaining, self.remaining_above_threshold, self.threshold
+        );
+    }
+
+    pub fn append(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+// Written when a session is interrupted so that --resume continues it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResumeState {
+    // The file that was playing when the session was interrupted.
+    pub current: Option<PathBuf>,
+    pub session: Session,
+}
+
+impl ResumeState {
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match File::open(path) {
+            Ok(file) => {
+                let mut state: ResumeState = serde_json::from_reader(BufReader::new(file))?;
+                state.session.prior_secs = state.session.duration_secs;
+                Ok(Some(state))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        info!("Saved session {:?}, continue with --resume", path);
+        Ok(())
+    }
+
+    pub fn remove(path: &Path) -> io::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::tokenizer::{Ngram, Tokenizer};
+use crate::{phash, CommonArgs, Label, Model};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SimilarArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The file to find similar files to, for example an episode of a series.
+    #[clap(long)]
+    file: PathBuf,
+
+    /// Print this many of the most similar files.
+    #[clap(long, default_value = "20")]
+    top: usize,
+
+    /// Add this weight times the pHash similarity of the sampled frames, decoded with ffmpeg and
+    /// kept in the --cache file.
+    #[clap(long)]
+    phash_weight: Option<f64>,
+}
+
+// Unit length ngram vectors, weighted by inverse document frequency over the library so that
+// ngrams shared by every file, such as the extension, count for little.
+fn vectors(tokenizer: &Tokenizer, paths: &[PathBuf]) -> Vec<HashMap<Ngram, f64>> {
+    let counts: Vec<HashMap<Ngram, f64>> = paths
+        .par_iter()
+        .map(|path| {
+            let mut counts: HashMap<Ngram, f64> = HashMap::new();
+            for ngram in tokenizer.ngrams_cached(path) {
+                // The unknown ngram.
+                if ngram.id() != 0 {
+                    *counts.entry(ngram).or_default() += 1.0;
+                }
+            }
+            counts
+        })
+        .collect();
+
+    let mut df: HashMap<Ngram, f64> = HashMap::new();
+    for counts in &counts {
+        for ngram in counts.keys() {
+            *df.entry(*ngram).or_default() += 1.0;
+        }
+    }
+
+    let n = paths.len() as f64;
+    counts
+        .into_iter()
+        .map(|mut counts| {
+            for (ngram, count) in counts.iter_mut() {
+                *count *= (n / df[ngram]).ln();
+            }
+            let norm = counts.values().map(|v| v * v).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                counts.values_mut().for_each(|v| *v /= norm);
+            }
+            counts
+        })
+        .collect()
+}
+
+fn cosine(a: &HashMap<Ngram, f64>, b: &HashMap<Ngram, f64>) -> f64 {
+    let (a, b) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    a.iter()
+        .filter_map(|(ngram, v)| Some(v * b.get(ngram)?))
+        .sum()
+}
+
+// Rank the library files, labeled or not, by similarity to the given file.
+pub fn run(args: &SimilarArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+
+    let mut labels: HashMap<PathBuf, Label> = HashMap::new();
+    for path in model.delete.iter() {
+        labels.insert(path, Label::Delete);
+    }
+    for path in model.keep.iter() {
+        labels.insert(path, Label::Keep);
+    }
+
+    let mut paths: Vec<PathBuf> = model
+        .files
+        .into_keys()
+        .chain(labels.keys().cloned())
+        .collect();
+    // The target as the library names it, which may differ from the form given, for example
+    // "./dir/x.mkv" for "dir/x.mkv", so that it is left out of the results and tokenized like
+    // the library files.
+    let target = match paths.iter().position(|path| path == &args.file) {
+        Some(i) => paths.swap_remove(i),
+        None => {
+            let canonical = args.file.canonicalize().ok();
+            let i = canonical.as_ref().and_then(|canonical| {
+                paths
+                    .par_iter()
+                    .position_any(|path| path.canonicalize().ok().as_ref() == Some(canonical))
+            });
+            match i {
+                Some(i) => paths.swap_remove(i),
+                None => args.file.clone(),
+            }
+        }
+    };
+    paths.sort();
+    // The target is scored along with the library so that its ngrams count towards the
+    // document frequencies.
+    paths.push(target.clone());
+
+    let mut vectors = vectors(&model.tokenizer, &paths);
+    let target_vector = vectors.pop().unwrap();
+    paths.pop();
+
+    let phashes = match args.phash_weight {
+        Some(_) => {
+            let mut all = paths.clone();
+            all.push(target.clone());
+            phash::hashes(common, &all)?
+        }
+        None => HashMap::new(),
+    };
+    let target_phash = phashes.get(&target);
+
+    let mut ranked: Vec<(f64, &Path)> = paths
+        .iter()
+        .zip(&vectors)
+        .map(|(path, vector)| {
+            let mut similarity = cosine(&target_vector, vector);
+            if let (Some(weight), Some(a), Some(b)) =
+                (args.phash_weight, target_phash, phashes.get(path))
+            {
+                similarity += weight * phash::similarity(a, b);
+            }
+            (similarity, path.as_path())
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+
+    for (similarity, path) in ranked.into_iter().take(args.top) {
+        let tag = labels.get(path).map(Label::as_str).unwrap_or_default();
+        println!("{:>6.3}  {:<6}  {}", similarity, tag, path.display());
+    }
+    Ok(())
+}
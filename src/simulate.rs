@@ -0,0 +1,135 @@
+use crate::evaluate::auc;
+use crate::{CommonArgs, FileState, Label, Model, NgramIndex};
+use log::*;
+use std::io;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SimulateArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The log base for the file size mixed into the score, as in interactive mode.
+    #[clap(long)]
+    file_size_log_base: Option<f64>,
+
+    /// Print a progress row every N labels.
+    #[clap(long, default_value = "10")]
+    every: usize,
+}
+
+// Replay the labeled files from an untrained model, revealing each label when its file is
+// picked in the same order interactive mode would have played it.
+pub fn run(args: &SimulateArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+    let tokenizer = &model.tokenizer;
+    let mut classifier = model.untrained();
+
+    let labeled: Vec<_> = model
+        .delete
+        .iter()
+        .map(|path| (path, Label::Delete))
+        .chain(model.keep.iter().map(|path| (path, Label::Keep)))
+        .collect();
+    if labeled.is_empty() {
+        error!("No labeled files to simulate");
+        return Ok(());
+    }
+
+    let mut labels = Vec::with_capacity(labeled.len());
+    let mut files: Vec<FileState> = Vec::with_capacity(labeled.len());
+    for (path, label) in labeled {
+        let file_size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let ngrams = tokenizer.ngrams_cached(&path);
+        let mut file = FileState::new(
+            path,
+            ngrams,
+            file_size,
+            args.file_size_log_base,
+            common.seed,
+        );
+        file.init(&classifier, tokenizer);
+        files.push(file);
+        labels.push(label);
+    }
+    let index = NgramIndex::new(&files);
+
+    let total_delete = labels.iter().filter(|l| **l == Label::Delete).count();
+    let mut remaining: Vec<usize> = (0..files.len()).collect();
+    let mut found = 0;
+    let mut all_found_at = None;
+
+    println!(
+        "{:>6} {:>7} {:>9} {:>9}",
+        "labels", "deletes", "precision", "auc_rest"
+    );
+    for step in 1..=files.len() {
+        let norm_log_odds = classifier.norm_log_odds();
+        for i in &remaining {
+            files[*i].update(norm_log_odds);
+        }
+
+        // The highest scoring file, with the same tie breaking as interactive mode.
+        let (pos, _) = remaining
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| {
+                let (a, b) = (&files[**a], &files[**b]);
+                a.score
+                    .total_cmp(&b.score)
+                    .then_with(|| a.tie_break.cmp(&b.tie_break))
+                    .then_with(|| a.path.cmp(&b.path))
+            })
+            .unwrap();
+        let i = remaining.swap_remove(pos);
+
+        let label = labels[i];
+        if label == Label::Delete {
+            found += 1;
+            if found == total_delete {
+                all_found_at = Some(step);
+            }
+        }
+        let ngrams = files[i].ngrams(tokenizer).into_owned();
+        index.train(&mut files, &mut classifier, label, &ngrams);
+        if classifier.has_meta() {
+            classifier.train_meta(label, &files[i].path);
+            for i in &remaining {
+                files[*i].meta_score = classifier.meta_log_odds(&files[*i].path);
+            }
+        }
+        debug!("{:?} ({})", files[i].path, label.as_str().to_uppercase());
+
+        if step % args.every.max(1) == 0 || step == files.len() {
+            // How well the current model ranks the files still hidden.
+            let norm_log_odds = classifier.norm_log_odds();
+            let scores: Vec<(Label, f64)> = remaining
+                .iter()
+                .map(|i| {
+                    files[*i].update(norm_log_odds);
+                    (labels[*i], files[*i].score)
+                })
+                .collect();
+            println!(
+                "{:>6} {:>7} {:>9.3} {:>9.3}",
+                step,
+                found,
+                found as f64 / step as f64,
+                auc(&scores)
+            );
+        }
+    }
+
+    println!();
+    match all_found_at {
+        Some(step) => println!(
+            "Found all {} deletes after {} of {} labels",
+            total_delete,
+            step,
+            files.len()
+        ),
+        None => println!("No deletes among {} labels", files.len()),
+    }
+
+    Ok(())
+}
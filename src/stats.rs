@@ -0,0 +1,136 @@
+use crate::{date, CommonArgs, Label, Model};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Print the stats as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Counts {
+    delete: usize,
+    keep: usize,
+    unlabeled: usize,
+}
+
+impl Counts {
+    fn inc(&mut self, label: Option<Label>) {
+        match label {
+            Some(Label::Delete) => self.delete += 1,
+            Some(Label::Keep) => self.keep += 1,
+            None => self.unlabeled += 1,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Stats {
+    total: Counts,
+    tokens: u32,
+    ngrams: u32,
+    extensions: BTreeMap<String, Counts>,
+    directories: BTreeMap<String, Counts>,
+    // Labels per UTC day they were made, "unknown" for labels made before timestamps were
+    // recorded.
+    labeled: BTreeMap<String, Counts>,
+}
+
+// The first directory below the scanned root containing path, or the parent directory for
+// files outside every root.
+fn top_level_dir(roots: &[impl AsRef<Path>], path: &Path) -> String {
+    for root in roots {
+        let root = root.as_ref();
+        if let Ok(rel) = path.strip_prefix(root) {
+            let mut components = rel.components();
+            let first = components.next();
+            return match (first, components.next()) {
+                (Some(dir), Some(_)) => root.join(dir).to_string_lossy().to_string(),
+                _ => root.to_string_lossy().to_string(),
+            };
+        }
+    }
+    path.parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+pub fn run(args: &StatsArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+
+    let mut stats = Stats {
+        tokens: model.tokenizer.token_count,
+        ngrams: model.tokenizer.ngram_count,
+        ..Default::default()
+    };
+
+    for (label, state) in [(Label::Delete, &model.delete), (Label::Keep, &model.keep)] {
+        for path in state.iter() {
+            let day = state
+                .timestamp(&path)
+                .map(date)
+                .unwrap_or_else(|| "unknown".to_string());
+            stats.labeled.entry(day).or_default().inc(Some(label));
+        }
+    }
+
+    let labeled = model
+        .delete
+        .iter()
+        .map(|path| (path, Some(Label::Delete)))
+        .chain(model.keep.iter().map(|path| (path, Some(Label::Keep))));
+    let unlabeled = model.files.keys().map(|path| (path.clone(), None));
+
+    for (path, label) in labeled.chain(unlabeled) {
+        stats.total.inc(label);
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        stats.extensions.entry(ext).or_default().inc(label);
+        let dir = top_level_dir(&common.paths, &path);
+        stats.directories.entry(dir).or_default().inc(label);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    println!(
+        "Files: {} delete, {} keep, {} unlabeled",
+        stats.total.delete, stats.total.keep, stats.total.unlabeled
+    );
+    println!("Tokens: {}", stats.tokens);
+    println!("Ngrams: {}", stats.ngrams);
+
+    for (title, counts) in [
+        ("Extension", &stats.extensions),
+        ("Directory", &stats.directories),
+    ] {
+        println!();
+        println!(
+            "{:>7} {:>7} {:>9}  {}",
+            "delete", "keep", "unlabeled", title
+        );
+        for (k, c) in counts {
+            println!("{:>7} {:>7} {:>9}  {}", c.delete, c.keep, c.unlabeled, k);
+        }
+    }
+
+    println!();
+    println!("{:>7} {:>7}  Labeled", "delete", "keep");
+    for (day, c) in &stats.labeled {
+        println!("{:>7} {:>7}  {}", c.delete, c.keep, day);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use crate::header::Header;
+use crate::migrate::replace;
+use crate::{date, CommonArgs, Label, State};
+use log::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SyncArgs {
+    /// The --delete and --keep files of this machine.
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The delete file of the other machine.
+    #[clap(long)]
+    remote_delete: PathBuf,
+
+    /// The keep file of the other machine.
+    #[clap(long)]
+    remote_keep: PathBuf,
+
+    /// The delete file as of the last sync, the common ancestor of both sides. With it, a
+    /// label changed on one side only wins and a label removed on one side stays removed.
+    #[clap(long, requires = "base_keep")]
+    base_delete: Option<PathBuf>,
+
+    /// The keep file as of the last sync.
+    #[clap(long, requires = "base_delete")]
+    base_keep: Option<PathBuf>,
+
+    /// Print the merge without writing the label files.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+// The label and label time of each entry of one side, in file order.
+#[derive(Debug, Default)]
+struct Side {
+    labels: HashMap<String, (Label, Option<u64>)>,
+    order: Vec<String>,
+    header: Option<Header>,
+}
+
+impl Side {
+    fn load(delete: &Path, keep: &Path) -> io::Result<Self> {
+        let mut side = Side::default();
+        for (label, path) in [(Label::Delete, delete), (Label::Keep, keep)] {
+            let state = State::from(path)?;
+            side.header = side.header.or(state.header.clone());
+            for entry in &state.contents {
+                let time = state.times.get(entry).cloned();
+                match side.labels.get(entry) {
+                    // In both files of one side, the later label wins.
+                    Some((_, other)) if time <= *other => {}
+                    Some(_) => {
+                        side.labels.insert(entry.clone(), (label, time));
+                    }
+                    None => {
+                        side.labels.insert(entry.clone(), (label, time));
+                        side.order.push(entry.clone());
+                    }
+                }
+            }
+        }
+        Ok(side)
+    }
+
+    fn label(&self, entry: &str) -> Option<Label> {
+        self.labels.get(entry).map(|(label, _)| *label)
+    }
+}
+
+fn describe(side: &str, label: Label, time: Option<u64>) -> String {
+    match time {
+        Some(time) => format!("{} {} on {}", side, label.as_str(), date(time)),
+        None => format!("{} {}", side, label.as_str()),
+    }
+}
+
+// Ask which label a disagreement resolves to, None to leave the file unlabeled.
+fn ask(entry: &str, local: String, remote: String) -> io::Result<Option<Label>> {
+    let mut stdin = io::stdin().lock();
+    loop {
+        print!(
+            "{}: {}, {}. [d]elete, [k]eep or [s]kip? ",
+            entry, local, remote
+        );
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        match line.trim() {
+            "d" => return Ok(Some(Label::Delete)),
+            "k" => return Ok(Some(Label::Keep)),
+            "s" => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+// Write the merged labels over one side's label files and timestamps.
+fn write(
+    delete: &Path,
+    keep: &Path,
+    header: &Header,
+    merged: &[(String, Label, Option<u64>)],
+) -> io::Result<()> {
+    for (label, path) in [(Label::Delete, delete), (Label::Keep, keep)] {
+        let mut lines = header.lines();
+        let mut times = Vec::new();
+        for (entry, _, time) in merged.iter().filter(|(_, l, _)| *l == label) {
+            lines.push(entry.clone());
+            if let Some(time) = time {
+                times.push(format!("{}\t{}", time, entry));
+            }
+        }
+        replace(path, &lines)?;
+        let mut times_path = path.to_path_buf().into_os_string();
+        times_path.push(".times");
+        replace(Path::new(&times_path), &times)?;
+    }
+    Ok(())
+}
+
+// Merge the label files of two machines. A file labeled on one side only takes that label.
+// Where the labels differ, the side that changed since --base-delete and --base-keep wins, then
+// the later label time. Disagreements neither decides, where a label time is missing, are
+// asked about on a terminal and otherwise keep their label on each side until the next sync.
+// Both sides are rewritten with the merge, keeping the old files as .bak.
+pub fn run(args: &SyncArgs) -> io::Result<()> {
+    let common = &args.common;
+    let local = Side::load(&common.delete, &common.keep)?;
+    let remote = Side::load(&args.remote_delete, &args.remote_keep)?;
+    let base = match (&args.base_delete, &args.base_keep) {
+        (Some(delete), Some(keep)) => Some(Side::load(delete, keep)?),
+        _ => None,
+    };
+    let interactive = io::stdin().is_terminal() && !args.dry_run;
+
+    let mut seen: HashSet<&String> = HashSet::new();
+    let mut merged: Vec<(String, Label, Option<u64>)> = Vec::new();
+    // Disagreements left for the next sync, each side keeping its own label meanwhile.
+    let (mut local_only, mut remote_only) = (Vec::new(), Vec::new());
+    let mut conflicts = 0;
+    for entry in local.order.iter().chain(&remote.order) {
+        if !seen.insert(entry) {
+            continue;
+        }
+        let base_label = base.as_ref().and_then(|base| base.label(entry));
+        let resolved = match (local.labels.get(entry), remote.labels.get(entry)) {
+            (Some(&(l, lt)), Some(&(r, rt))) if l == r => Some((l, lt.max(rt))),
+            (Some(&(l, lt)), Some(&(r, rt))) => {
+                conflicts += 1;
+                let local_desc = describe("local", l, lt);
+                let remote_desc = describe("remote", r, rt);
+                let resolved = if base_label == Some(l) {
+                    Some((r, rt))
+                } else if base_label == Some(r) {
+                    Some((l, lt))
+                } else {
+                    match (lt, rt) {
+                        (Some(lt), Some(rt)) if lt > rt => Some((l, Some(lt))),
+                        (Some(lt), Some(rt)) if rt > lt => Some((r, Some(rt))),
+                        _ if interactive => ask(entry, local_desc.clone(), remote_desc.clone())?
+                            .map(|label| (label, Some(crate::now_secs()))),
+                        _ => None,
+                    }
+                };
+                match resolved {
+                    Some((label, _)) => println!(
+                        "{}: {}, {}, merged {}",
+                        entry,
+                        local_desc,
+                        remote_desc,
+                        label.as_str()
+                    ),
+                    None => {
+                        println!("{}: {}, {}, unresolved", entry, local_desc, remote_desc);
+                        local_only.push((entry.clone(), l, lt));
+                        remote_only.push((entry.clone(), r, rt));
+                    }
+                }
+                resolved
+            }
+            // Labeled on one side only: new there, or removed on the other side since the base.
+            (Some(&(label, time)), None) | (None, Some(&(label, time))) => {
+                (base_label != Some(label)).then_some((label, time))
+            }
+            (None, None) => None,
+        };
+        if let Some((label, time)) = resolved {
+            merged.push((entry.clone(), label, time));
+        }
+    }
+
+    println!(
+        "{} files merged, {} labeled differently, {} unresolved",
+        merged.len(),
+        conflicts,
+        local_only.len()
+    );
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let header = local
+        .header
+        .or(remote.header)
+        .unwrap_or_else(|| Header::new(common));
+    local_only.splice(0..0, merged.iter().cloned());
+    remote_only.splice(0..0, merged);
+    write(&common.delete, &common.keep, &header, &local_only)?;
+    write(
+        &args.remote_delete,
+        &args.remote_keep,
+        &header,
+        &remote_only,
+    )?;
+    info!("Wrote the merged labels to both sides, the old files are kept as .bak");
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use crate::dupes::{probe_duration, probe_pool};
+use crate::{CommonArgs, FileState, Model};
+use log::*;
+use rayon::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ThumbsArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// The directory contact sheets are written to, by default "<cache>.thumbs" next to the
+    /// --cache file or "thumbs".
+    #[clap(long)]
+    thumbs_dir: Option<PathBuf>,
+
+    /// Only make sheets for this many of the best ranked files.
+    #[clap(long)]
+    top: Option<usize>,
+
+    /// Columns and rows of frames in each sheet.
+    #[clap(long, default_value = "4x3")]
+    grid: String,
+
+    /// The width of each frame in pixels.
+    #[clap(long, default_value = "320")]
+    width: u32,
+}
+
+// The sheet file name, derived from the path, size and modification time so sheets of changed
+// files are remade.
+fn sheet_name(path: &Path, size: u64, modified: u64) -> String {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    size.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    format!("{:016x}.jpg", hasher.finish())
+}
+
+// Tile evenly spaced frames of a video into a single image with ffmpeg.
+fn contact_sheet(path: &Path, out: &Path, grid: &str, width: u32) -> io::Result<()> {
+    let (cols, rows) = grid
+        .split_once('x')
+        .and_then(|(c, r)| Some((c.parse::<u64>().ok()?, r.parse::<u64>().ok()?)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--grid {:?}, expected COLSxROWS", grid),
+            )
+        })?;
+    let duration = probe_duration(path)
+        .filter(|d| *d > 0)
+        .ok_or_else(|| io::Error::other(format!("No duration for {:?}", path)))?;
+    let interval = duration as f64 / (cols * rows) as f64;
+
+    let status = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-i"])
+        .arg(path)
+        .arg("-vf")
+        .arg(format!(
+            "fps=1/{:.3},scale={}:-2,tile={}x{}",
+            interval, width, cols, rows
+        ))
+        .args(["-frames:v", "1"])
+        .arg(out)
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "ffmpeg failed for {:?}: {}",
+            path, status
+        )));
+    }
+    Ok(())
+}
+
+// Make a contact sheet per unlabeled file, best delete candidates first, reusing existing sheets.
+// Prints "path<TAB>sheet" lines.
+pub fn run(args: &ThumbsArgs) -> io::Result<()> {
+    let common = &args.common;
+    let model = Model::load(common)?;
+    let tokenizer = &model.tokenizer;
+    let classifier = &model.classifier;
+
+    let dir = match (&args.thumbs_dir, &common.cache) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(cache)) => {
+            let mut dir = cache.clone().into_os_string();
+            dir.push(".thumbs");
+            PathBuf::from(dir)
+        }
+        (None, None) => PathBuf::from("thumbs"),
+    };
+    std::fs::create_dir_all(&dir)?;
+
+    let norm_log_odds = classifier.norm_log_odds();
+    let mut files: Vec<(FileState, u64)> = model
+        .files
+        .into_par_iter()
+        .map(|(path, meta)| {
+            let ngrams = tokenizer.ngrams_cached(&path);
+            let mut file = FileState::new(path, ngrams, meta.size, None, common.seed);
+            file.init(classifier, tokenizer);
+            file.update(norm_log_odds);
+            file.ngrams = None;
+            (file, meta.modified)
+        })
+        .collect();
+    files.sort_by(|(a, _), (b, _)| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.tie_break.cmp(&a.tie_break))
+            .then_with(|| b.path.cmp(&a.path))
+    });
+    files.truncate(args.top.unwrap_or(files.len()));
+
+    let sheets: Vec<(PathBuf, PathBuf)> = probe_pool(common)?.install(|| {
+        files
+            .par_iter()
+            .filter_map(|(file, modified)| {
+                let sheet = dir.join(sheet_name(&file.path, file.file_size, *modified));
+                if !sheet.exists() {
+                    if let Err(e) = contact_sheet(&file.path, &sheet, &args.grid, args.width) {
+                        error!("{}", e);
+                        return None;
+                    }
+                }
+                Some((file.path.clone(), sheet))
+            })
+            .collect()
+    });
+
+    for (path, sheet) in sheets {
+        println!("{}\t{}", path.display(), sheet.display());
+    }
+    Ok(())
+}
@@ -1,42 +1,241 @@
+use crate::cache::{Cache, Counts, Vocab};
+use crate::media::Media;
+use crate::progress;
+use crate::walk::FileMeta;
+use crate::CommonArgs;
+use indicatif::ProgressIterator;
 use log::*;
-use std::collections::{BTreeSet, HashMap};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+// Years like (2019), season and episode markers like S01E02 and episode numbers like EP12,
+// delimited by non-alphanumeric characters.
+const STRUCTURED: &str = r"(?:^|[^[:alnum:]])(?P<token>(?P<year>(?:19|20)\d{2})|s(?P<season>\d{1,2})e\d{1,3}|ep\d{1,3})(?:$|[^[:alnum:]])";
+
+// A named regex whose captures over the lowercased path are added as extra tokens.
+#[derive(Debug, Clone)]
+pub struct FeatureRegex {
+    name: String,
+    re: Regex,
+}
+
+impl FromStr for FeatureRegex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, re) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected NAME:REGEX, got {:?}", s))?;
+        let re = Regex::new(re).map_err(|e| e.to_string())?;
+        Ok(Self {
+            name: name.to_string(),
+            re,
+        })
+    }
+}
+
+impl FeatureRegex {
+    // One token per capture group, or for the whole match when there are none.
+    fn tokens(&self, path: &str, ret: &mut Vec<String>) {
+        for caps in self.re.captures_iter(path) {
+            let mut groups = caps.iter().skip(1).flatten().peekable();
+            if groups.peek().is_none() {
+                ret.push(format!("<{}:{}>", self.name, &caps[0]));
+            }
+            for m in groups {
+                ret.push(format!("<{}:{}>", self.name, m.as_str()));
+            }
+        }
+    }
+}
+
+// The ngram sizes: "5" for 1 to 5 tokens, "2..5" for 2 to 5 or a list like "1,3,5".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Windows(Vec<usize>);
+
+impl FromStr for Windows {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = |v: &str| match v.trim().parse::<usize>() {
+            Ok(0) => Err(format!("{:?}: ngram sizes start at 1", s)),
+            Ok(n) => Ok(n),
+            Err(e) => Err(format!("{:?}: {}", s, e)),
+        };
+        let mut sizes: Vec<usize> = if let Some((from, to)) = s.split_once("..") {
+            let to = to.strip_prefix('=').unwrap_or(to);
+            (size(from)?..=size(to)?).collect()
+        } else if s.contains(',') {
+            s.split(',').map(size).collect::<Result<_, _>>()?
+        } else {
+            (1..=size(s)?).collect()
+        };
+        sizes.sort();
+        sizes.dedup();
+        if sizes.is_empty() {
+            return Err(format!("{:?}: no ngram sizes", s));
+        }
+        Ok(Self(sizes))
+    }
+}
+
+// The shortest form that parses back to the same sizes.
+impl std::fmt::Display for Windows {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (first, last) = (self.0[0], self.0[self.0.len() - 1]);
+        if self.0.len() == last - first + 1 {
+            if first == 1 {
+                write!(f, "{}", last)
+            } else {
+                write!(f, "{}..{}", first, last)
+            }
+        } else {
+            let sizes: Vec<String> = self.0.iter().map(usize::to_string).collect();
+            write!(f, "{}", sizes.join(","))
+        }
+    }
+}
+
+// Insert spaces at camelCase and letter-digit boundaries, "MovieName2019" becomes
+// "Movie Name 2019" and "HDRVideo" becomes "HDR Video".
+fn split_case(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut ret = String::with_capacity(s.len() + 8);
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 {
+            let prev = chars[i - 1];
+            let next = chars.get(i + 1);
+            if (prev.is_lowercase() && c.is_uppercase())
+                || (prev.is_alphabetic() && c.is_numeric())
+                || (prev.is_numeric() && c.is_alphabetic())
+                || (prev.is_uppercase()
+                    && c.is_uppercase()
+                    && next.is_some_and(|n| n.is_lowercase()))
+            {
+                ret.push(' ');
+            }
+        }
+        ret.push(*c);
+    }
+    ret
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tokenize {
     Words,
     Chars,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Copy, Clone, Default)]
-pub struct Token(u32);
+#[derive(
+    Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Copy, Clone, Default, Serialize, Deserialize,
+)]
+pub struct Token(pub(crate) u32);
 
-#[derive(Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Copy, Clone, Default)]
+#[derive(
+    Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Copy, Clone, Default, Serialize, Deserialize,
+)]
 pub struct Ngram(u32);
 
+impl Ngram {
+    pub fn id(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+// The tokens of an ngram, stored inline up to four tokens rather than in an allocation of its
+// own, since there are millions of ngrams in a large library.
+pub type NgramTokens = SmallVec<[Token; 4]>;
+
 #[derive(Debug)]
 pub struct Tokenizer {
     pub tokenize: Tokenize,
 
     // Token state.
-    token_count: u32,
+    pub token_count: u32,
     pub token_string: HashMap<Token, String>,
     string_token: HashMap<String, Token>,
 
     // Ngram state.
-    windows: usize,
+    windows: Windows,
+    skipgrams: bool,
     pub ngram_count: u32,
-    pub ngram_tokens: HashMap<Ngram, Vec<Token>>,
-    tokens_ngram: HashMap<Vec<Token>, Ngram>,
+    pub ngram_tokens: HashMap<Ngram, NgramTokens>,
+    tokens_ngram: HashMap<NgramTokens, Ngram>,
+    // Inverse document frequency by ngram id, the unknown ngram has no weight.
+    pub ngram_idf: Option<Vec<f64>>,
+
+    // Tokenization results by path.
+    cache: Cache,
+    // The ngrams of the files the vocabulary was built from.
+    file_ngrams: HashMap<PathBuf, Vec<Ngram>>,
+
+    // Words removed before ngram generation.
+    stop_tokens: HashSet<String>,
+
+    // Matches years, season and episode markers to replace with structured tokens.
+    structured: Option<Regex>,
+
+    feature_regexes: Vec<FeatureRegex>,
+
+    split_case: bool,
+
+    // The fraction of a path's words missing from the vocabulary above which their character
+    // ngrams are added.
+    char_fallback: Option<f64>,
+
+    // Media server metadata, added as tokens after the cached path tokens.
+    pub media: Media,
+}
+
+// Count each key of a file once, or uncount the keys of a file removed from the library.
+fn add_counts<K: Eq + Hash>(counts: &mut HashMap<K, usize>, keys: BTreeSet<K>, removed: bool) {
+    for key in keys {
+        if !removed {
+            *counts.entry(key).or_default() += 1;
+        } else if let Some(count) = counts.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&key);
+            }
+        }
+    }
 }
 
 impl Tokenizer {
-    pub fn new(tokenize: Tokenize, windows: usize, files: &HashMap<PathBuf, u64>) -> Self {
-        assert!(windows > 0);
+    pub fn new(
+        common: &CommonArgs,
+        files: &HashMap<PathBuf, FileMeta>,
+        mut cache: Cache,
+    ) -> io::Result<Self> {
+        let tokenize = common.tokenize;
+        let windows = common.windows.clone();
+        let min_freq = common.min_freq;
+        let max_vocab = common.max_vocab.unwrap_or(usize::MAX);
 
         let file_count = files.len();
         assert!(file_count > 0);
 
+        cache.retain(|path| files.contains_key(path));
+
+        let mut stop_tokens: HashSet<String> = common.stop_tokens.iter().cloned().collect();
+        if let Some(path) = &common.stop_tokens_file {
+            for line in BufReader::new(File::open(path)?).lines() {
+                stop_tokens.insert(line?.trim().to_string());
+            }
+        }
+        stop_tokens.remove("");
+        let stop_tokens = stop_tokens
+            .into_iter()
+            .map(|t| t.to_ascii_lowercase())
+            .collect();
+
         let mut tokenizer = Self {
             tokenize,
 
@@ -45,29 +244,159 @@ impl Tokenizer {
             token_string: HashMap::new(),
 
             windows,
+            skipgrams: common.skipgrams,
             ngram_count: 0,
             ngram_tokens: HashMap::new(),
             tokens_ngram: HashMap::new(),
+            ngram_idf: common.idf.then(|| vec![0.0]),
+
+            cache: Cache::default(),
+            file_ngrams: HashMap::new(),
+
+            stop_tokens,
+
+            structured: common
+                .structured_tokens
+                .then(|| Regex::new(STRUCTURED).unwrap()),
+
+            feature_regexes: common.feature_regex.clone(),
+
+            split_case: common.split_case,
+
+            char_fallback: match common.tokenize {
+                Tokenize::Words => common.char_fallback,
+                Tokenize::Chars => None,
+            },
+
+            media: Media::load(&common.media)?,
         };
 
-        // Unique token count per file.
-        let mut token_counts: HashMap<String, usize> = HashMap::new();
-        for path in files.keys() {
-            let mut tokens = tokenizer.tokenize_new(path);
-            tokens.sort();
-            tokens.dedup();
-            for token in tokens {
-                let e = token_counts.entry(token).or_default();
-                *e += 1;
+        for (path, meta) in files
+            .iter()
+            .progress_with(progress::bar(file_count, "Tokenizing"))
+        {
+            if cache.get(path, meta).is_none() {
+                cache.insert(path, meta, tokenizer.tokenize_new(path));
             }
         }
+        tokenizer.cache = cache;
+
+        // Media server metadata is fetched fresh each run, so a vocabulary including it is not
+        // cached.
+        let vocab_key = tokenizer
+            .media
+            .is_empty()
+            .then(|| Self::vocab_key(common, &tokenizer.stop_tokens));
+        if let Some(vocab) = vocab_key
+            .as_ref()
+            .and_then(|key| tokenizer.cache.vocab(key))
+        {
+            tokenizer.token_string = (1..).map(Token).zip(vocab.tokens.clone()).collect();
+            tokenizer.string_token = (1..)
+                .map(Token)
+                .zip(vocab.tokens.clone())
+                .map(|(t, s)| (s, t))
+                .collect();
+            tokenizer.token_count = vocab.tokens.len() as u32;
+            tokenizer.ngram_tokens = (1..).map(Ngram).zip(vocab.ngrams.clone()).collect();
+            tokenizer.tokens_ngram = (1..)
+                .map(Ngram)
+                .zip(vocab.ngrams.clone())
+                .map(|(n, t)| (t, n))
+                .collect();
+            tokenizer.ngram_count = vocab.ngrams.len() as u32;
+            tokenizer.ngram_idf = vocab.idf.clone();
+            tokenizer.file_ngrams = vocab.files.clone();
+            info!("File count: {} (cached vocabulary)", file_count);
+            info!("Token count: {}", tokenizer.token_count);
+            info!("Ngram count: {}", tokenizer.ngram_count);
+            return Ok(tokenizer);
+        }
+
+        // The files the vocabulary is built from, a uniform sample of a library larger than
+        // --tokenizer-sample.
+        let sample: Vec<&PathBuf> = match common.tokenizer_sample {
+            Some(n) if n < file_count => {
+                let mut paths: Vec<(u64, &PathBuf)> = files
+                    .keys()
+                    .map(|path| (crate::seeded_hash(common.seed, path), path))
+                    .collect();
+                paths.sort();
+                paths.truncate(n.max(1));
+                info!("Building the vocabulary from {} sampled files", paths.len());
+                paths.into_iter().map(|(_, path)| path).collect()
+            }
+            _ => files.keys().collect(),
+        };
+        let sample_count = sample.len();
+        // A file count in the sample scaled to the whole library. Something in one sampled file
+        // may well be unique to it, so that count is not scaled.
+        let scale = |count: usize| match count {
+            0 | 1 => count,
+            _ => count * file_count / sample_count,
+        };
+
+        // The counts of the last run, updated for the files added and removed since rather than
+        // counted again. A sample changes with the library, so its counts are not kept.
+        let sampled = sample_count < file_count;
+        let saved = match &vocab_key {
+            Some(key) if !sampled => tokenizer.cache.take_counts(key),
+            _ => None,
+        };
+        let (counted, removed): (Vec<&PathBuf>, Vec<PathBuf>) = match &saved {
+            Some(saved) => {
+                let counted: Vec<&PathBuf> = sample
+                    .iter()
+                    .filter(|path| !saved.paths.contains(**path))
+                    .cloned()
+                    .collect();
+                let removed: Vec<PathBuf> = saved
+                    .paths
+                    .iter()
+                    .filter(|path| !files.contains_key(*path))
+                    .cloned()
+                    .collect();
+                info!(
+                    "Updating the cached counts for {} added and {} removed files",
+                    counted.len(),
+                    removed.len()
+                );
+                (counted, removed)
+            }
+            None => (sample.clone(), Vec::new()),
+        };
+        let (mut token_counts, saved_ngrams) = match saved {
+            Some(saved) => (saved.tokens, Some((saved.vocab, saved.ngrams))),
+            None => (HashMap::new(), None),
+        };
+
+        // Unique token count per file.
+        let token_set = |path: &Path| -> BTreeSet<String> {
+            tokenizer.tokenize_strings(path).into_iter().collect()
+        };
+        for path in counted
+            .iter()
+            .progress_with(progress::bar(counted.len(), "Counting tokens"))
+        {
+            add_counts(&mut token_counts, token_set(path), false);
+        }
+        for path in &removed {
+            add_counts(&mut token_counts, token_set(path), true);
+        }
+
+        // Most frequent first, so a capped vocabulary keeps the most useful tokens.
+        let mut sorted_tokens: Vec<(String, usize)> = token_counts
+            .iter()
+            .map(|(token, count)| (token.clone(), scale(*count)))
+            .collect();
+        sorted_tokens.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
 
         let mut unique_tokens: BTreeSet<String> = BTreeSet::new();
         let mut common_tokens: BTreeSet<String> = BTreeSet::new();
-        for (token, count) in token_counts {
-            if count > 1 {
+        for (token, count) in sorted_tokens {
+            if count >= min_freq && (tokenizer.token_count as usize) < max_vocab {
                 tokenizer.make_token(&token);
-            } else if count == 1 {
+            } else if count < min_freq {
                 unique_tokens.insert(token);
             } else if count == file_count {
                 common_tokens.insert(token);
@@ -76,21 +405,74 @@ impl Tokenizer {
         //debug!("Drop unique tokens: {:?}", unique_tokens);
         //debug!("Drop common tokens: {:?}", common_tokens);
 
-        let mut ngram_counts: HashMap<Vec<Token>, usize> = HashMap::new();
-        for path in files.keys() {
-            let ngrams: BTreeSet<Vec<Token>> = tokenizer.ngrams_new(path).into_iter().collect();
-            for ngram in ngrams {
-                let e = ngram_counts.entry(ngram).or_default();
-                *e += 1;
+        // The character ngrams depend on which words made it into the vocabulary, so they are
+        // counted after it, over every sampled file.
+        if tokenizer.char_fallback.is_some() {
+            let mut char_counts: HashMap<String, usize> = HashMap::new();
+            for path in sample
+                .iter()
+                .progress_with(progress::bar(sample_count, "Counting character ngrams"))
+            {
+                let strings = tokenizer.tokenize_strings(path);
+                let grams = tokenizer.fallback_grams(&strings).into_iter().collect();
+                add_counts(&mut char_counts, grams, false);
+            }
+            let mut sorted_grams: Vec<(String, usize)> = char_counts
+                .into_iter()
+                .map(|(gram, count)| (gram, scale(count)))
+                .filter(|(_, count)| *count >= min_freq)
+                .collect();
+            sorted_grams.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            // The grams count toward --max-vocab too, filling what the words left of it.
+            for (gram, _) in sorted_grams {
+                if tokenizer.token_count as usize >= max_vocab {
+                    break;
+                }
+                tokenizer.make_token(&gram);
+            }
+        }
+
+        // Ngrams are counted in token ids, so the saved counts only hold while the token
+        // vocabulary is unchanged.
+        let vocab: Vec<String> = (1..=tokenizer.token_count)
+            .map(|id| tokenizer.token_string[&Token(id)].clone())
+            .collect();
+        let (mut ngram_counts, counted, removed) = match saved_ngrams {
+            Some((saved_vocab, ngrams)) if saved_vocab == vocab => {
+                let ngrams: HashMap<NgramTokens, usize> = ngrams.into_iter().collect();
+                (ngrams, counted, removed)
             }
+            _ => (HashMap::new(), sample.clone(), Vec::new()),
+        };
+        let ngram_set = |path: &Path| -> BTreeSet<NgramTokens> {
+            tokenizer.ngrams_new(path).into_iter().collect()
+        };
+        for path in counted
+            .iter()
+            .progress_with(progress::bar(counted.len(), "Counting ngrams"))
+        {
+            add_counts(&mut ngram_counts, ngram_set(path), false);
+        }
+        for path in &removed {
+            add_counts(&mut ngram_counts, ngram_set(path), true);
         }
 
-        let mut unique_ngrams: BTreeSet<Vec<Token>> = BTreeSet::new();
-        let mut common_ngrams: BTreeSet<Vec<Token>> = BTreeSet::new();
-        for (ngram, count) in ngram_counts {
-            if count > 1 {
+        // Assign ngram ids in a stable order.
+        let mut sorted_ngrams: Vec<(NgramTokens, usize)> = ngram_counts
+            .iter()
+            .map(|(ngram, count)| (ngram.clone(), scale(*count)))
+            .collect();
+        sorted_ngrams.sort();
+
+        let mut unique_ngrams: BTreeSet<NgramTokens> = BTreeSet::new();
+        let mut common_ngrams: BTreeSet<NgramTokens> = BTreeSet::new();
+        for (ngram, count) in sorted_ngrams {
+            if count >= min_freq {
                 tokenizer.make_ngram(&ngram);
-            } else if count == 1 {
+                if let Some(idf) = &mut tokenizer.ngram_idf {
+                    idf.push((file_count as f64 / count as f64).ln());
+                }
+            } else if count < min_freq {
                 unique_ngrams.insert(ngram);
             } else if count == file_count {
                 common_ngrams.insert(ngram);
@@ -99,11 +481,40 @@ impl Tokenizer {
         //debug!("Drop unique ngrams: {:?}", unique_ngrams);
         //debug!("Drop common ngrams: {:?}", common_ngrams);
 
+        if let (Some(key), false) = (&vocab_key, sampled) {
+            tokenizer.cache.set_counts(Counts {
+                key: key.clone(),
+                paths: files.keys().cloned().collect(),
+                tokens: token_counts,
+                vocab,
+                ngrams: ngram_counts.into_iter().collect(),
+            });
+        }
+
+        tokenizer.file_ngrams = files
+            .keys()
+            .map(|path| (path.clone(), tokenizer.ngrams_cached(path)))
+            .collect();
+        if let Some(key) = vocab_key {
+            let vocab = Vocab {
+                key,
+                tokens: (1..=tokenizer.token_count)
+                    .map(|id| tokenizer.token_string[&Token(id)].clone())
+                    .collect(),
+                ngrams: (1..=tokenizer.ngram_count)
+                    .map(|id| tokenizer.ngram_tokens[&Ngram(id)].clone())
+                    .collect(),
+                idf: tokenizer.ngram_idf.clone(),
+                files: tokenizer.file_ngrams.clone(),
+            };
+            tokenizer.cache.set_vocab(vocab);
+        }
+
         info!("File count: {}", file_count);
         info!("Token count: {}", tokenizer.token_count);
         info!("Ngram count: {}", tokenizer.ngram_count);
 
-        tokenizer
+        Ok(tokenizer)
     }
 
     fn make_token(&mut self, s: &str) -> Token {
@@ -128,17 +539,80 @@ impl Tokenizer {
         self.ngram_count += 1;
         let ngram = Ngram(self.ngram_count);
 
-        self.tokens_ngram.insert(tokens.to_vec(), ngram);
-        self.ngram_tokens.insert(ngram, tokens.to_vec());
+        self.tokens_ngram.insert(tokens.into(), ngram);
+        self.ngram_tokens.insert(ngram, tokens.into());
 
         ngram
     }
 
+    // The settings that change tokenize_new output, to invalidate cached tokens.
+    pub fn cache_key(common: &CommonArgs) -> String {
+        let feature_regexes: Vec<String> = common
+            .feature_regex
+            .iter()
+            .map(|f| format!("{}:{}", f.name, f.re))
+            .collect();
+        format!(
+            "{:?} structured_tokens={} feature_regexes={:?} split_case={}",
+            common.tokenize, common.structured_tokens, feature_regexes, common.split_case
+        )
+    }
+
+    // The settings that change the vocabulary built from the tokens.
+    fn vocab_key(common: &CommonArgs, stop_tokens: &HashSet<String>) -> String {
+        let mut stop_tokens: Vec<&String> = stop_tokens.iter().collect();
+        stop_tokens.sort();
+        format!(
+            "windows={} skipgrams={} min_freq={} max_vocab={:?} idf={} stop_tokens={:?} sample={:?} char_fallback={:?}",
+            common.windows,
+            common.skipgrams,
+            common.min_freq,
+            common.max_vocab,
+            common.idf,
+            stop_tokens,
+            common.tokenizer_sample.map(|n| (n, common.seed)),
+            common.char_fallback
+        )
+    }
+
     fn tokenize_new(&self, path: &Path) -> Vec<String> {
-        let mut path: String = path.to_string_lossy().to_string();
+        // Ascii lowercasing keeps byte offsets, so matches on path index into original too.
+        let original: String = path.to_string_lossy().to_string();
+        let mut path = original.clone();
         path.make_ascii_lowercase();
 
         let mut ret = Vec::new();
+        let mut last = 0;
+        if let Some(re) = &self.structured {
+            for caps in re.captures_iter(&path) {
+                let m = caps.name("token").unwrap();
+                self.tokenize_str(&original[last..m.start()], &mut ret);
+                if let Some(year) = caps.name("year") {
+                    ret.push(format!("<YEAR:{}>", year.as_str()));
+                } else if caps.name("season").is_some() {
+                    ret.push("<SEASON>".to_string());
+                    ret.push("<EPISODE>".to_string());
+                } else {
+                    ret.push("<EPISODE>".to_string());
+                }
+                last = m.end();
+            }
+        }
+        self.tokenize_str(&original[last..], &mut ret);
+        for feature_regex in &self.feature_regexes {
+            feature_regex.tokens(&path, &mut ret);
+        }
+        ret
+    }
+
+    fn tokenize_str(&self, path: &str, ret: &mut Vec<String>) {
+        let mut path = if self.split_case {
+            split_case(path)
+        } else {
+            path.to_string()
+        };
+        path.make_ascii_lowercase();
+
         match self.tokenize {
             Tokenize::Words => {
                 for token in path
@@ -153,37 +627,190 @@ impl Tokenizer {
                     if c.is_alphanumeric() || c == '/' {
                         ret.push(c.into());
                         continue;
+                    } else if c == '\\' {
+                        // Windows separators tokenize like Unix ones.
+                        ret.push('/'.into());
+                        continue;
                     } else if Some(" ") != ret.last().map(|x| x.as_str()) {
                         ret.push(' '.into());
                     }
                 }
             }
         }
-        ret
     }
 
+    fn tokenize_strings(&self, path: &Path) -> Vec<String> {
+        let tokens = match self.cache.tokens(path) {
+            Some(tokens) => tokens.to_vec(),
+            None => self.tokenize_new(path),
+        };
+        let mut tokens = self.remove_stop_tokens(tokens);
+        if let Some(info) = self.media.get(path) {
+            tokens.extend(info.tokens());
+        }
+        tokens
+    }
+
+    fn remove_stop_tokens(&self, tokens: Vec<String>) -> Vec<String> {
+        if self.stop_tokens.is_empty() {
+            return tokens;
+        }
+        match self.tokenize {
+            Tokenize::Words => tokens
+                .into_iter()
+                .filter(|t| !self.stop_tokens.contains(t))
+                .collect(),
+            Tokenize::Chars => {
+                // Regroup runs of alphanumeric chars into words to match the stop tokens.
+                let mut ret: Vec<String> = Vec::new();
+                let mut word: Vec<String> = Vec::new();
+                let flush = |ret: &mut Vec<String>, word: &mut Vec<String>| {
+                    if !self.stop_tokens.contains(&word.concat()) {
+                        ret.append(word);
+                    }
+                    word.clear();
+                };
+                for token in tokens {
+                    if token.chars().all(char::is_alphanumeric) {
+                        word.push(token);
+                        continue;
+                    }
+                    flush(&mut ret, &mut word);
+                    if token == " " && Some(" ") == ret.last().map(|x| x.as_str()) {
+                        continue;
+                    }
+                    ret.push(token);
+                }
+                flush(&mut ret, &mut word);
+                ret
+            }
+        }
+    }
+
+    // Write the cache and free it. Once the vocabulary is built, the tokens of every file, the
+    // cached vocabulary and the counts are only needed on disk, paths are tokenized again on
+    // demand.
+    pub fn save_cache(&mut self) -> io::Result<()> {
+        self.cache.save()?;
+        self.cache = Cache::default();
+        Ok(())
+    }
+
+    // Free the ngrams of the library files, for callers keeping their own copy of them. They
+    // are computed again on demand.
+    pub fn release_file_ngrams(&mut self) {
+        self.file_ngrams = HashMap::new();
+    }
+
+    // The tokens of a path, followed by its --char-fallback character ngrams in the vocabulary.
     pub fn tokenize_cached(&self, path: &Path) -> Vec<Token> {
+        let strings = self.tokenize_strings(path);
+        let mut ret = self.to_tokens(&strings);
+        for gram in self.fallback_grams(&strings) {
+            ret.extend(self.string_token.get(&gram));
+        }
+        ret
+    }
+
+    fn to_tokens(&self, strings: &[String]) -> Vec<Token> {
+        strings
+            .iter()
+            .map(|s| self.string_token.get(s).cloned().unwrap_or_default())
+            .collect()
+    }
+
+    // The character 3 to 5 grams of the words missing from the vocabulary, as "<chars:abc>"
+    // tokens, when they make up at least the --char-fallback fraction of the tokens.
+    fn fallback_grams(&self, strings: &[String]) -> Vec<String> {
+        let Some(fraction) = self.char_fallback else {
+            return Vec::new();
+        };
+        let unknown: Vec<&String> = strings
+            .iter()
+            .filter(|s| !s.starts_with('<') && !self.string_token.contains_key(*s))
+            .collect();
+        if strings.is_empty() || (unknown.len() as f64) < fraction * strings.len() as f64 {
+            return Vec::new();
+        }
         let mut ret = Vec::new();
-        for token in self.tokenize_new(path) {
-            ret.push(self.string_token.get(&token).cloned().unwrap_or_default());
+        for word in unknown {
+            let chars: Vec<char> = word.chars().collect();
+            for n in 3..=5 {
+                for w in chars.windows(n) {
+                    ret.push(format!("<chars:{}>", w.iter().collect::<String>()));
+                }
+            }
         }
         ret
     }
 
-    fn ngrams_new(&self, path: &Path) -> Vec<Vec<Token>> {
-        let tokens = self.tokenize_cached(path);
+    fn ngrams_new(&self, path: &Path) -> Vec<NgramTokens> {
+        let strings = self.tokenize_strings(path);
+        let tokens = self.to_tokens(&strings);
         let mut ret = Vec::new();
-        for i in 0..self.windows {
-            for w in tokens.windows(i + 1) {
-                let mut w: Vec<Token> = w.to_vec();
-                w.shrink_to_fit();
-                ret.push(w);
+        for &size in &self.windows.0 {
+            for w in tokens.windows(size) {
+                ret.push(NgramTokens::from_slice(w));
+            }
+        }
+        if self.skipgrams {
+            // Windows one token longer with a single interior token left out.
+            for &i in self.windows.0.iter().filter(|size| **size >= 2) {
+                for w in tokens.windows(i + 1) {
+                    for skip in 1..i {
+                        let mut w = NgramTokens::from_slice(w);
+                        w.remove(skip);
+                        ret.push(w);
+                    }
+                }
+            }
+        }
+        // Character ngrams stand alone, they are not windowed with the words.
+        for gram in self.fallback_grams(&strings) {
+            if let Some(token) = self.string_token.get(&gram) {
+                ret.push(NgramTokens::from_slice(&[*token]));
             }
         }
         ret
     }
 
+    // The text of an ngram, unknown tokens are shown as "*".
+    pub fn ngram_string(&self, ngram: &Ngram) -> Option<String> {
+        Some(self.tokens_string(self.ngram_tokens.get(ngram)?))
+    }
+
+    // The text of a sequence of tokens, unknown tokens are shown as "*".
+    pub fn tokens_string(&self, tokens: &[Token]) -> String {
+        let mut v = Vec::new();
+        for token in tokens {
+            if let Some(s) = self.token_string.get(token) {
+                v.push(s.to_string());
+            } else {
+                v.push(String::from("*"));
+            }
+        }
+        match self.tokenize {
+            Tokenize::Chars => v.join(""),
+            Tokenize::Words => v.join(" "),
+        }
+    }
+
+    // The text and id of each ngram of a string tokenized like a path, the ngrams missing from
+    // the vocabulary with the unknown id.
+    pub fn text_ngrams(&self, text: &str) -> Vec<(String, Ngram)> {
+        self.ngrams_new(Path::new(text))
+            .into_iter()
+            .map(|tokens| {
+                let ngram = self.tokens_ngram.get(&tokens).cloned().unwrap_or_default();
+                (self.tokens_string(&tokens), ngram)
+            })
+            .collect()
+    }
+
     pub fn ngrams_cached(&self, path: &Path) -> Vec<Ngram> {
+        if let Some(ngrams) = self.file_ngrams.get(path) {
+            return ngrams.clone();
+        }
         let mut ret = Vec::new();
         for ngram in self.ngrams_new(path) {
             ret.push(self.tokens_ngram.get(&ngram).cloned().unwrap_or_default());
@@ -191,3 +818,21 @@ impl Tokenizer {
         ret
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows() {
+        let parse = |s: &str| s.parse::<Windows>().map(|w| (w.0.clone(), w.to_string()));
+        assert_eq!(parse("3"), Ok((vec![1, 2, 3], "3".to_string())));
+        assert_eq!(parse("2..4"), Ok((vec![2, 3, 4], "2..4".to_string())));
+        assert_eq!(parse("2..=4"), Ok((vec![2, 3, 4], "2..4".to_string())));
+        assert_eq!(parse("5,1,3"), Ok((vec![1, 3, 5], "1,3,5".to_string())));
+        assert_eq!(parse("1,2"), Ok((vec![1, 2], "2".to_string())));
+        assert!(parse("0").is_err());
+        assert!(parse("4..2").is_err());
+        assert!(parse("a").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use clap::ValueEnum;
+use plotters::coord::Shift;
+use plotters::prelude::{
+    BitMapBackend, ChartBuilder, Color, DrawingArea, DrawingBackend, IntoDrawingArea, Rectangle,
+    SVGBackend, BLUE, WHITE,
+};
+use std::io;
+use std::path::Path;
+use textplots::{Chart, Plot, Shape};
+
+const BINS: usize = 40;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Viz {
+    /// Score histograms with the bin of the next file marked.
+    Histogram,
+    /// Scores plotted against their rank.
+    Line,
+    /// No charts.
+    Off,
+}
+
+pub struct ScoreVisualizer {
+    viz: Viz,
+}
+
+impl ScoreVisualizer {
+    pub fn new(viz: Viz) -> Self {
+        Self { viz }
+    }
+
+    // Plot scores ordered by ascending total score, marking the score of the next file.
+    pub fn plot(&self, title: &str, scores: &[f64], next: f64) {
+        match self.viz {
+            Viz::Histogram => histogram(title, scores, next),
+            Viz::Line => line(title, scores),
+            Viz::Off => {}
+        }
+    }
+}
+
+// The lower bound, width and counts of BINS equal width bins spanning the scores.
+fn bins(scores: &[f64]) -> (f64, f64, Vec<usize>) {
+    let mut min = scores.iter().cloned().fold(f64::INFINITY, f64::min);
+    let mut max = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max <= min {
+        min -= 0.5;
+        max += 0.5;
+    }
+    let width = (max - min) / BINS as f64;
+    let mut counts = vec![0; BINS];
+    for score in scores {
+        counts[bin(min, width, *score)] += 1;
+    }
+    (min, width, counts)
+}
+
+fn bin(min: f64, width: f64, score: f64) -> usize {
+    (((score - min) / width) as usize).min(BINS - 1)
+}
+
+fn line(title: &str, scores: &[f64]) {
+    let mut xmin = 0.0;
+    let mut xmax = 0.0;
+    let mut ymin = 0.0;
+    let mut ymax = 0.0;
+    let mut points = Vec::new();
+    for (i, y) in scores.iter().enumerate() {
+        let (x, y) = (i as f32, *y as f32);
+        xmin = f32::min(xmin, x);
+        xmax = f32::max(xmax, x);
+        ymin = f32::min(ymin, y);
+        ymax = f32::max(ymax, y);
+        points.push((x, y));
+    }
+    println!("{} scores", title);
+    Chart::new_with_y_range(300, 80, xmin, xmax, ymin, ymax)
+        .lineplot(&Shape::Points(&points))
+        .nice();
+}
+
+fn histogram(title: &str, scores: &[f64], current: f64) {
+    if scores.is_empty() {
+        return;
+    }
+    let (min, width, counts) = bins(scores);
+    let bars: Vec<(f32, f32)> = counts
+        .iter()
+        .enumerate()
+        .map(|(i, count)| ((min + i as f64 * width) as f32, *count as f32))
+        .collect();
+
+    // A vertical line through the bin of the next file.
+    let ymax = counts.iter().cloned().max().unwrap_or_default() as f32;
+    let x = (min + bin(min, width, current) as f64 * width) as f32;
+    let marker = [(x, 0.0), (x, ymax)];
+
+    let max = (min + BINS as f64 * width) as f32;
+    println!("{} scores (next file {:.3})", title, current);
+    Chart::new_with_y_range(300, 80, min as f32, max, 0.0, ymax)
+        .lineplot(&Shape::Bars(&bars))
+        .lineplot(&Shape::Lines(&marker))
+        .nice();
+}
+
+// Render a histogram per score column, stacked vertically, to an SVG or PNG file.
+pub fn export(path: &Path, columns: &[(&str, Vec<f64>)]) -> io::Result<()> {
+    let size = (1024, 320 * columns.len().max(1) as u32);
+    let is_png = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        draw(BitMapBackend::new(path, size).into_drawing_area(), columns)
+    } else {
+        draw(SVGBackend::new(path, size).into_drawing_area(), columns)
+    }
+}
+
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    columns: &[(&str, Vec<f64>)],
+) -> io::Result<()> {
+    let err = |e: &dyn std::fmt::Display| io::Error::other(e.to_string());
+    root.fill(&WHITE).map_err(|e| err(&e))?;
+
+    let areas = root.split_evenly((columns.len().max(1), 1));
+    for ((title, scores), area) in columns.iter().zip(areas) {
+        if scores.is_empty() {
+            continue;
+        }
+        let (min, width, counts) = bins(scores);
+        let max = min + BINS as f64 * width;
+        let ymax = counts.iter().cloned().max().unwrap_or_default() as f64;
+
+        let mut chart = ChartBuilder::on(&area)
+            .caption(format!("{} scores", title), ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .build_cartesian_2d(min..max, 0.0..ymax * 1.05)
+            .map_err(|e| err(&e))?;
+        chart
+            .configure_mesh()
+            .x_desc("score")
+            .y_desc("files")
+            .draw()
+            .map_err(|e| err(&e))?;
+        chart
+            .draw_series(counts.iter().enumerate().map(|(i, count)| {
+                let x0 = min + i as f64 * width;
+                Rectangle::new([(x0, 0.0), (x0 + width, *count as f64)], BLUE.filled())
+            }))
+            .map_err(|e| err(&e))?;
+    }
+
+    root.present().map_err(|e| err(&e))?;
+    Ok(())
+}
@@ -1,8 +1,66 @@
+use crate::pathmap::{self, PathMap};
 use crate::Error;
 use log::*;
 use serde::Deserialize;
-use std::path::Path;
-use std::process::{Child, Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str::FromStr;
+
+// A point in or duration of playback, either a percentage of the length ("30%") or seconds
+// ("20s" or "20").
+#[derive(Debug, Clone, Copy)]
+pub enum Offset {
+    Percent(f64),
+    Seconds(f64),
+}
+
+impl Offset {
+    pub fn seconds(&self, length: f64) -> f64 {
+        match self {
+            Offset::Percent(p) => length * p / 100.0,
+            Offset::Seconds(s) => *s,
+        }
+    }
+}
+
+impl FromStr for Offset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|e| format!("{:?}: {}", s, e))
+        };
+        if let Some(p) = s.strip_suffix('%') {
+            Ok(Offset::Percent(parse(p)?))
+        } else {
+            Ok(Offset::Seconds(parse(s.strip_suffix('s').unwrap_or(s))?))
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct VlcArgs {
+    /// Fullscreen VLC playback.
+    #[clap(short, long)]
+    fullscreen: bool,
+
+    #[clap(long, default_value = "9010")]
+    vlc_port: u16,
+
+    /// The VLC executable, for example a wrapper script for flatpak or snap installs.
+    #[clap(long, default_value = "vlc")]
+    vlc_path: PathBuf,
+
+    /// An extra argument passed to VLC, may be repeated, for example --vlc-arg=--no-audio.
+    #[clap(long, allow_hyphen_values = true)]
+    vlc_arg: Vec<String>,
+
+    /// How many times playback of a file is restarted after VLC crashes before skipping it.
+    #[clap(long, default_value = "3")]
+    pub vlc_restarts: usize,
+}
 
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
@@ -20,6 +78,16 @@ impl Status {
             .map(|i| i.category.meta.filename.clone())
     }
 
+    // The playback position as a fraction of the length.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    // The length in seconds.
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
     pub fn state(&self) -> &str {
         self.state.as_str()
     }
@@ -40,14 +108,29 @@ pub struct Meta {
     filename: String,
 }
 
+// A VLC process with the HTTP interface kept alive across files, respawned when it exits.
 pub struct VLCProcessHandle {
+    args: VlcArgs,
     handle: Option<Child>,
     status_url: String,
+    path_map: Vec<PathMap>,
 }
 
 impl VLCProcessHandle {
-    pub fn new(args: &crate::Args, path: &Path) -> Self {
-        let mut command = Command::new("vlc");
+    pub fn new(args: &VlcArgs, path_map: &[PathMap]) -> Self {
+        VLCProcessHandle {
+            args: args.clone(),
+            handle: None,
+            status_url: format!(
+                "http://:password@localhost:{}/requests/status.json",
+                args.vlc_port
+            ),
+            path_map: path_map.to_vec(),
+        }
+    }
+
+    fn spawn(&mut self) -> Result<(), Error> {
+        let mut command = Command::new(&self.args.vlc_path);
         command
             .args([
                 "-I",
@@ -62,30 +145,130 @@ impl VLCProcessHandle {
                 "password",
                 "--http-port",
             ])
-            .arg(format!("{}", args.vlc_port))
-            .arg(path)
+            .arg(format!("{}", self.args.vlc_port))
+            .args(&self.args.vlc_arg)
             .stdout(Stdio::null())
             .stderr(Stdio::null());
 
-        if args.fullscreen {
+        if self.args.fullscreen {
             command.arg("--fullscreen");
         }
 
         debug!("Spawn {:?}", command);
 
-        let child = command.spawn().expect("Failed to start VLC process");
+        self.handle = Some(command.spawn().expect("Failed to start VLC process"));
 
-        VLCProcessHandle {
-            handle: Some(child),
-            status_url: format!(
-                "http://:password@localhost:{}/requests/status.json",
-                args.vlc_port
-            ),
+        for _ in 0..100 {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            if self.status().is_ok() {
+                return Ok(());
+            }
         }
+        Err(Error::Timeout)
+    }
+
+    fn running(&mut self) -> bool {
+        match self.handle.as_mut().map(|child| child.try_wait()) {
+            Some(Ok(None)) => true,
+            Some(result) => {
+                warn!("VLC exited {:?}, restarting", result);
+                self.kill();
+                false
+            }
+            None => false,
+        }
+    }
+
+    // The exit status once VLC has exited, waiting briefly for it to finish exiting.
+    pub fn exit_status(&mut self) -> Option<ExitStatus> {
+        for _ in 0..10 {
+            match self.handle.as_mut()?.try_wait() {
+                Ok(Some(status)) => {
+                    self.handle = None;
+                    return Some(status);
+                }
+                Ok(None) => std::thread::sleep(std::time::Duration::from_millis(100)),
+                Err(e) => {
+                    error!("wait {:?}", e);
+                    return None;
+                }
+            }
+        }
+        None
+    }
+
+    // Replace the playlist with the given files and start playing the first one. Each file
+    // with an OSD text shows it overlaid on the video for a few seconds.
+    pub fn play(&mut self, paths: &[&Path], osd: &[String]) -> Result<(), Error> {
+        if !self.running() {
+            self.spawn()?;
+        }
+        self.command(&[("command", "pl_empty")])?;
+        for (i, path) in paths.iter().enumerate() {
+            let command = if i == 0 { "in_play" } else { "in_enqueue" };
+            self.add(command, path, osd.get(i))?;
+        }
+        Ok(())
+    }
+
+    // Move on to the file queued after the current one, which VLC has already opened, and
+    // queue the files after it. Starts over as play does when VLC is not running.
+    pub fn play_queued(&mut self, paths: &[&Path], osd: &[String]) -> Result<(), Error> {
+        if !self.running() {
+            return self.play(paths, osd);
+        }
+        self.command(&[("command", "pl_next")])?;
+        for (i, path) in paths.iter().enumerate().skip(1) {
+            self.add("in_enqueue", path, osd.get(i))?;
+        }
+        Ok(())
+    }
+
+    fn add(&self, command: &str, path: &Path, osd: Option<&String>) -> Result<(), Error> {
+        // A mapped path may be a URL for VLC to open itself, such as smb://nas/media/a.mkv.
+        let input = match pathmap::map(&self.path_map, path) {
+            Some(mapped) if mapped.contains("://") => mapped,
+            Some(mapped) => file_url(Path::new(&mapped))?,
+            None => file_url(path)?,
+        };
+        let mut query = vec![("command", command), ("input", input.as_str())];
+        let marquee;
+        if let Some(text) = osd {
+            marquee = format!(":marq-marquee={}", text);
+            query.extend([
+                ("option", ":sub-source=marq"),
+                ("option", marquee.as_str()),
+                ("option", ":marq-timeout=5000"),
+            ]);
+        }
+        self.command(&query)?;
+        Ok(())
+    }
+
+    fn command(&self, query: &[(&str, &str)]) -> Result<Status, Error> {
+        let mut url = reqwest::Url::parse(&self.status_url).expect("Invalid status URL");
+        url.query_pairs_mut().extend_pairs(query);
+        // VLC decodes %20 but not the + that form encoding uses for spaces.
+        let encoded = url.query().map(|q| q.replace('+', "%20"));
+        url.set_query(encoded.as_deref());
+        self.request(url.as_str())
     }
 
     pub fn status(&self) -> Result<Status, Error> {
-        let response = reqwest::blocking::get(&self.status_url)?;
+        self.request(&self.status_url)
+    }
+
+    pub fn stop(&self) -> Result<Status, Error> {
+        self.command(&[("command", "pl_stop")])
+    }
+
+    // Seek to an absolute position in seconds.
+    pub fn seek(&self, seconds: f64) -> Result<Status, Error> {
+        self.command(&[("command", "seek"), ("val", &seconds.round().to_string())])
+    }
+
+    fn request(&self, url: &str) -> Result<Status, Error> {
+        let response = reqwest::blocking::get(url)?;
         let text = response.text()?;
         debug!("Response: {}", text);
         Ok(serde_json::from_str(&text)?)
@@ -102,10 +285,8 @@ impl VLCProcessHandle {
         }
         Err(Error::Timeout)
     }
-}
 
-impl Drop for VLCProcessHandle {
-    fn drop(&mut self) {
+    fn kill(&mut self) {
         if let Some(mut child) = self.handle.take() {
             let kill_result = child.kill();
             debug!("kill {:?}", kill_result);
@@ -114,3 +295,16 @@ impl Drop for VLCProcessHandle {
         }
     }
 }
+
+fn file_url(path: &Path) -> Result<String, Error> {
+    let path = std::path::absolute(path)?;
+    let url = reqwest::Url::from_file_path(&path)
+        .map_err(|_| std::io::Error::other(format!("Invalid path {:?}", path)))?;
+    Ok(url.into())
+}
+
+impl Drop for VLCProcessHandle {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
@@ -1,22 +1,200 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
 use log::*;
-use std::collections::{HashMap, HashSet};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsString;
+use std::fs::Metadata;
+use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
+// Identifies a file or directory independently of the path it was reached through.
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(unix)]
+fn file_id(_path: &Path, m: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _m: &Metadata) -> Option<FileId> {
+    std::fs::canonicalize(path).ok()
+}
+
+// A Windows path in the form the directory walk produces: backslash separators, an upper case
+// drive letter, no repeated separators and no \\?\ prefix from canonicalization. Label files,
+// --files-from lists and media servers may write the same file as "c:/videos//a.mkv".
+fn normalize_windows(path: &str) -> String {
+    let path = path.replace('/', "\\");
+    let path = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        path
+    };
+    let unc = path.starts_with(r"\\");
+    let drive = path.as_bytes().get(1) == Some(&b':');
+    let mut ret = String::with_capacity(path.len());
+    for (i, c) in path.chars().enumerate() {
+        let c = if i == 0 && drive {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        };
+        // The leading \\ of a UNC path is kept.
+        if c == '\\' && ret.ends_with('\\') && !(unc && i == 1) {
+            continue;
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+// Normalize a path from outside the directory walk to match the walked paths, see
+// normalize_windows. Other platforms use paths as given.
+pub fn normalize(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(normalize_windows(&path.to_string_lossy()))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn dir_id(path: &Path) -> Option<FileId> {
+    let m = std::fs::metadata(path).ok()?;
+    file_id(path, &m)
+}
+
+// Equal when the size and modification time match, which is what cached results depend on.
+#[derive(Debug, Clone, Copy, Eq, Default, Serialize, Deserialize)]
+pub struct FileMeta {
+    pub size: u64,
+    // Seconds since the unix epoch.
+    pub modified: u64,
+    // The birth time where the platform and filesystem record it, otherwise the modification
+    // time.
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default)]
+    pub accessed: u64,
+}
+
+impl PartialEq for FileMeta {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.modified == other.modified
+    }
+}
+
+fn secs(time: std::io::Result<std::time::SystemTime>) -> Option<u64> {
+    time.ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+impl FileMeta {
+    pub fn new(m: &Metadata) -> Self {
+        let modified = secs(m.modified()).unwrap_or_default();
+        Self {
+            size: m.len(),
+            modified,
+            created: secs(m.created()).unwrap_or(modified),
+            accessed: secs(m.accessed()).unwrap_or(modified),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct File {
+    path: PathBuf,
+    meta: FileMeta,
+    id: Option<FileId>,
+}
+
+impl File {
+    fn new(path: PathBuf, m: &Metadata) -> Self {
+        let id = file_id(&path, m);
+        Self {
+            path,
+            meta: FileMeta::new(m),
+            id,
+        }
+    }
+}
+
+const IGNORE_FILE: &str = ".classignore";
+
+// Load the .classignore file in dir, if present.
+fn load_ignore(dir: &Path) -> Option<Gitignore> {
+    let path = dir.join(IGNORE_FILE);
+    if !path.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(&path) {
+        warn!("Ignore file {:?}: {}", path, e);
+    }
+    match builder.build() {
+        Ok(gitignore) => Some(gitignore),
+        Err(e) => {
+            warn!("Ignore file {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+// The deepest matching ignore file decides, as with .gitignore.
+fn is_ignored(ignores: &[Arc<Gitignore>], path: &Path, is_dir: bool) -> bool {
+    for gitignore in ignores.iter().rev() {
+        match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+    }
+    false
+}
+
+// How the library is walked, from the command line options.
+pub struct WalkOptions<'a> {
+    pub video_exts: &'a [String],
+    pub follow_symlinks: bool,
+    // Skip hardlinks and symlinks to files already found.
+    pub dedup: bool,
+    pub canonicalize: bool,
+    // Fail on the first walk error rather than skipping the directory.
+    pub strict: bool,
+}
+
 pub struct Walk {
     exts: HashSet<OsString>,
-    tx: Arc<Sender<Vec<(PathBuf, u64)>>>,
-    rx: Mutex<Receiver<Vec<(PathBuf, u64)>>>,
+    follow_symlinks: bool,
+    dedup: bool,
+    canonicalize: bool,
+    strict: bool,
+    // Walk errors per directory, summarized by collect or returned by it in strict mode.
+    errors: Mutex<BTreeMap<PathBuf, Vec<String>>>,
+    // Directories already walked, used to break symlink cycles.
+    visited: Mutex<HashSet<FileId>>,
+    tx: Arc<Sender<Vec<File>>>,
+    rx: Mutex<Receiver<Vec<File>>>,
 }
 
 impl Walk {
-    pub fn new(video_exts: &Vec<String>) -> Self {
+    // Walk the roots, more files can be added with files_from before collecting them.
+    pub fn new(roots: &[PathBuf], options: &WalkOptions) -> Self {
         let mut exts: HashSet<OsString> = HashSet::new();
-        for e in video_exts {
+        for e in options.video_exts {
             let mut e = OsString::from(e);
             e.make_ascii_lowercase();
             exts.insert(e);
@@ -24,23 +202,89 @@ impl Walk {
         let (tx, rx) = std::sync::mpsc::channel();
         let tx = Arc::new(tx);
         let rx = Mutex::new(rx);
-        Self { exts, tx, rx }
+        let walk = Self {
+            exts,
+            follow_symlinks: options.follow_symlinks,
+            dedup: options.dedup,
+            canonicalize: options.canonicalize,
+            strict: options.strict,
+            errors: Mutex::new(BTreeMap::new()),
+            visited: Mutex::new(HashSet::new()),
+            tx,
+            rx,
+        };
+        for root in roots {
+            walk.root(root);
+        }
+        walk
+    }
+
+    // Returns false if the directory has already been walked.
+    fn visit(&self, dir: &Path) -> bool {
+        if !self.follow_symlinks {
+            return true;
+        }
+        match dir_id(dir) {
+            Some(id) => self.visited.lock().unwrap().insert(id),
+            None => true,
+        }
+    }
+
+    fn root(&self, root: &Path) {
+        self.dir(&normalize(root), Vec::new());
     }
 
-    pub fn root(&self, root: &Path) {
+    fn error(&self, dir: &Path, e: impl std::fmt::Display) {
+        warn!("Walk error: {}", e);
+        let mut errors = self.errors.lock().unwrap();
+        errors
+            .entry(dir.to_path_buf())
+            .or_default()
+            .push(e.to_string());
+    }
+
+    fn dir(&self, root: &Path, mut ignores: Vec<Arc<Gitignore>>) {
+        // Stop early, the walk is abandoned.
+        if self.strict && !self.errors.lock().unwrap().is_empty() {
+            return;
+        }
+        if !self.visit(root) {
+            debug!("Skip visited {:?}", root);
+            return;
+        }
+
         info!("Walk {:?}", root);
 
+        if let Some(gitignore) = load_ignore(root) {
+            ignores.push(Arc::new(gitignore));
+        }
+
         rayon::scope(|s| {
             let mut files = Vec::new();
-            for e in WalkDir::new(root).max_depth(1) {
-                let e = e.unwrap();
+            for e in WalkDir::new(root)
+                .max_depth(1)
+                .follow_links(self.follow_symlinks)
+            {
+                let e = match e {
+                    Ok(e) => e,
+                    Err(e) => {
+                        self.error(root, e);
+                        continue;
+                    }
+                };
                 let path = e.path();
                 let ft = e.file_type();
 
+                if e.depth() == 1 && is_ignored(&ignores, path, ft.is_dir()) {
+                    debug!("Ignore {:?}", path);
+                    continue;
+                }
+
                 if ft.is_dir() && e.depth() == 1 {
                     let path = path.to_path_buf();
+                    let ignores = ignores.clone();
                     s.spawn(move |_| {
-                        self.root(&path);
+                        self.dir(&path, ignores);
                     });
                 } else if ft.is_file() {
                     match path.extension() {
@@ -51,24 +295,165 @@ impl Walk {
                         }
                         None => continue,
                     }
-                    let file = path.to_path_buf();
-                    let size = e.metadata().unwrap().len();
-                    files.push((file, size));
+                    match e.metadata() {
+                        Ok(m) => files.push(File::new(path.to_path_buf(), &m)),
+                        Err(e) => self.error(root, e),
+                    }
                 }
             }
             self.tx.send(files).unwrap();
         });
     }
 
-    pub fn collect(self) -> HashMap<PathBuf, u64> {
+    // Read newline separated file paths instead of walking a directory.
+    pub fn files_from(&self, reader: impl BufRead) {
+        let mut files = Vec::new();
+        for line in reader.lines().map_while(Result::ok) {
+            if line.is_empty() {
+                continue;
+            }
+            let path = normalize(Path::new(&line));
+            match std::fs::metadata(&path) {
+                Ok(m) if m.is_file() => files.push(File::new(path, &m)),
+                Ok(_) => warn!("Not a file {:?}", path),
+                Err(e) => warn!("File {:?}: {}", path, e),
+            }
+        }
+        info!("Files from list: {}", files.len());
+        self.tx.send(files).unwrap();
+    }
+
+    // The files found, or in strict mode the first walk error. Otherwise a summary of the
+    // errors per directory is logged, as the files in those directories are missing.
+    pub fn collect(self) -> io::Result<HashMap<PathBuf, FileMeta>> {
         drop(self.tx);
-        let mut ret = HashMap::new();
+        let mut files = Vec::new();
         let rx = self.rx.lock().unwrap();
         while let Ok(vec) = rx.recv() {
-            for (k, v) in vec {
-                ret.insert(k, v);
+            files.extend(vec);
+        }
+
+        let errors = self.errors.into_inner().unwrap();
+        if let Some((dir, e)) = errors.iter().next() {
+            if self.strict {
+                return Err(io::Error::other(format!(
+                    "walk error in {:?}: {}",
+                    dir, e[0]
+                )));
+            }
+            let count: usize = errors.values().map(Vec::len).sum();
+            warn!(
+                "{} walk errors in {} directories, their files are not candidates:",
+                count,
+                errors.len()
+            );
+            for (dir, e) in &errors {
+                warn!("  {:?}: {}", dir, e.len());
+            }
+        }
+
+        // Files that cannot be resolved keep the path they were found at.
+        if self.canonicalize {
+            files.par_iter_mut().for_each(|file| {
+                if let Ok(path) = std::fs::canonicalize(&file.path) {
+                    file.path = normalize(&path);
+                }
+            });
+        }
+
+        // Sort so the same path wins between hardlinks on every run.
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut seen: HashSet<FileId> = HashSet::new();
+        let mut ret = HashMap::new();
+        for file in files {
+            if self.dedup {
+                if let Some(id) = file.id {
+                    if !seen.insert(id) {
+                        debug!("Skip duplicate {:?}", file.path);
+                        continue;
+                    }
+                }
             }
+            ret.insert(file.path, file.meta);
         }
-        ret
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_windows, Walk, WalkOptions};
+
+    #[test]
+    fn drive_letters() {
+        assert_eq!(normalize_windows(r"c:\videos\a.mkv"), r"C:\videos\a.mkv");
+        assert_eq!(normalize_windows("C:/videos/a.mkv"), r"C:\videos\a.mkv");
+        assert_eq!(
+            normalize_windows(r"d:/videos\sub/a.mkv"),
+            r"D:\videos\sub\a.mkv"
+        );
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(
+            normalize_windows(r"C:\videos\\sub//a.mkv"),
+            r"C:\videos\sub\a.mkv"
+        );
+        assert_eq!(normalize_windows(r"videos\a.mkv"), r"videos\a.mkv");
+        assert_eq!(normalize_windows("videos/a.mkv"), r"videos\a.mkv");
+    }
+
+    #[test]
+    fn unc() {
+        assert_eq!(
+            normalize_windows(r"\\nas\media\a.mkv"),
+            r"\\nas\media\a.mkv"
+        );
+        assert_eq!(
+            normalize_windows("//nas/media//a.mkv"),
+            r"\\nas\media\a.mkv"
+        );
+    }
+
+    #[test]
+    fn verbatim() {
+        assert_eq!(
+            normalize_windows(r"\\?\C:\videos\a.mkv"),
+            r"C:\videos\a.mkv"
+        );
+        assert_eq!(
+            normalize_windows(r"\\?\UNC\nas\media\a.mkv"),
+            r"\\nas\media\a.mkv"
+        );
+    }
+
+    // A dangling symlink, followed, is a walk error.
+    #[cfg(unix)]
+    #[test]
+    fn strict_walk() {
+        let dir = std::env::temp_dir().join(format!("classi-cine-walk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.mkv"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("b.mkv")).unwrap();
+        let exts = vec!["mkv".to_string()];
+        let roots = [dir.clone()];
+        let mut options = WalkOptions {
+            video_exts: &exts,
+            follow_symlinks: true,
+            dedup: true,
+            canonicalize: false,
+            strict: false,
+        };
+
+        let walk = Walk::new(&roots, &options);
+        assert_eq!(walk.collect().unwrap().len(), 1);
+
+        options.strict = true;
+        let walk = Walk::new(&roots, &options);
+        assert!(walk.collect().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }